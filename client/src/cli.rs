@@ -0,0 +1,100 @@
+use chrono::NaiveDateTime;
+use clap::{Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use std::path::PathBuf;
+
+/// Distributed player node
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    /// Node config file
+    #[arg(
+        long,
+        env = "DISTRIBUTED_PLAYER_CONFIG",
+        default_value = "node_config.toml"
+    )]
+    pub config: PathBuf,
+
+    /// Media folder, overrides config file setting
+    #[arg(long, env = "DISTRIBUTED_PLAYER_MEDIA_FOLDER")]
+    pub media_folder: Option<String>,
+
+    /// Node name, overrides config file setting and host name
+    #[arg(long, env = "DISTRIBUTED_PLAYER_NODE_NAME")]
+    pub node_name: Option<String>,
+
+    /// Log level: off, error, warn, info, debug or trace, overrides config file setting
+    #[arg(long, env = "DISTRIBUTED_PLAYER_LOG_LEVEL")]
+    pub log_level: Option<LevelFilter>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Play content according to playlist (default)
+    Run,
+    /// Check node config and playlist files
+    Validate,
+    /// Print effective playlist settings with files they come from
+    Explain,
+    /// Print scheduled content for given period without playing it
+    Simulate {
+        /// Period start, current time by default
+        #[arg(long)]
+        from: Option<NaiveDateTime>,
+        /// Period end, one day after start by default
+        #[arg(long)]
+        to: Option<NaiveDateTime>,
+    },
+    /// Check media folders and files referenced by playlist
+    ScanLibrary,
+    /// Query status of running node over control channel
+    Status,
+    /// Synchronize media folder with content server once
+    Sync,
+    /// Write integrity manifest of media files referenced by playlist
+    Manifest {
+        /// Manifest file, cfg/manifest.json inside media folder by default
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// List nodes advertised over mDNS on LAN
+    Discover {
+        /// Seconds to wait for answers
+        #[arg(long, default_value_t = 3)]
+        timeout: u64,
+    },
+    /// Print JSON Schema of playlist documents or node settings for editor integration
+    Schema {
+        #[arg(value_enum, default_value_t = SchemaKind::Playlist)]
+        kind: SchemaKind,
+        /// Output file, standard output by default
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert playlist document or node settings between TOML, YAML and JSON, formats are given by extensions
+    Convert {
+        /// Source file
+        input: PathBuf,
+        /// Converted file
+        output: PathBuf,
+        /// Kind of settings file
+        #[arg(long, value_enum, default_value_t = SchemaKind::Playlist)]
+        kind: SchemaKind,
+    },
+    /// Roll playlist back on every node at once, run where playlist documents are stored
+    Rollback {
+        /// Version to roll back to, previous version of current rollout by default
+        #[arg(long)]
+        to: Option<String>,
+    },
+}
+
+/// Settings file described by JSON Schema
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SchemaKind {
+    Playlist,
+    NodeConfig,
+}
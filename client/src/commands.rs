@@ -0,0 +1,473 @@
+use crate::cli::SchemaKind;
+use crate::config::NodeConfig;
+use crate::discovery;
+use crate::error::{Error, Result};
+use crate::format::{self, Format};
+use crate::manifest;
+use crate::merge;
+use crate::player;
+use crate::playlist::{ContentType, Playlist};
+use crate::playlist_source::PlaylistSource;
+use crate::quarantine::Quarantine;
+use crate::rollout::{self, Rollout};
+use crate::schema;
+use crate::sync::Syncer;
+use chrono::{prelude::*, Duration};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+/// Prints node config and playlist problems including unknown keys, returns error if playlist
+/// can't be read or there are problems
+pub fn validate(node_config: &NodeConfig) -> Result<()> {
+    for field in node_config.unknown_fields.iter() {
+        println!("node config: {}", field);
+    }
+
+    let playlist = Playlist::read_from_config(node_config)?;
+    let unknown_fields = Playlist::find_unknown_fields(node_config)?;
+    for (origin, field) in unknown_fields.iter() {
+        println!("{}: {}", origin.to_string_lossy(), field);
+    }
+    let problems = playlist.validate();
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+
+    if !node_config.unknown_fields.is_empty() {
+        return Err(Error::ConfigInvalid(node_config.unknown_fields.len()));
+    }
+    if !problems.is_empty() || !unknown_fields.is_empty() {
+        return Err(Error::PlaylistInvalid(
+            problems.len() + unknown_fields.len(),
+        ));
+    }
+    println!("node config and playlist are valid");
+    Ok(())
+}
+
+/// Prints JSON Schema or writes it to file
+pub fn schema(kind: SchemaKind, output: Option<&Path>) -> Result<()> {
+    let schema = match kind {
+        SchemaKind::Playlist => schema::get_playlist_schema(),
+        SchemaKind::NodeConfig => schema::get_node_config_schema(),
+    };
+    let content = serde_json::to_string_pretty(&schema).unwrap_or_default();
+    match output {
+        Some(path) => fs::write(path, content + "\n").map_err(|source| Error::SchemaWrite {
+            path: path.to_path_buf(),
+            source,
+        }),
+        None => {
+            println!("{}", content);
+            Ok(())
+        }
+    }
+}
+
+/// Converts settings file to format given by output extension, merge directives are kept;
+/// the file is checked before conversion and its unknown keys are printed
+pub fn convert(kind: SchemaKind, input: &Path, output: &Path) -> Result<()> {
+    let to_error = |path: &Path, reason: String| Error::Convert {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let get_format = |path: &Path| {
+        Format::from_path(path).ok_or_else(|| {
+            to_error(
+                path,
+                format!("unsupported format, use {}", format::EXTENSIONS.join(", ")),
+            )
+        })
+    };
+    let (input_format, output_format) = (get_format(input)?, get_format(output)?);
+    let get_schema = match kind {
+        SchemaKind::Playlist => schema::get_playlist_schema,
+        SchemaKind::NodeConfig => schema::get_node_config_schema,
+    };
+
+    let content = fs::read_to_string(input).map_err(|e| to_error(input, e.to_string()))?;
+    let document = format::parse(input_format, &content, get_schema)
+        .map_err(|e| to_error(input, e.to_string()))?;
+    let mut stripped = document.clone();
+    merge::strip_directives(&mut stripped);
+    let stripped = toml::to_string(&stripped).map_err(|e| to_error(input, e.to_string()))?;
+    let unknown_fields = match kind {
+        SchemaKind::Playlist => {
+            schema::from_str::<Playlist>(&stripped, &get_schema()).map(|(_, fields)| fields)
+        }
+        SchemaKind::NodeConfig => {
+            schema::from_str::<NodeConfig>(&stripped, &get_schema()).map(|(_, fields)| fields)
+        }
+    }
+    .map_err(|e| to_error(input, e.to_string()))?;
+    for field in unknown_fields.iter() {
+        println!("{}: {}", input.to_string_lossy(), field);
+    }
+
+    let content =
+        format::format(output_format, &document).map_err(|reason| to_error(output, reason))?;
+    fs::write(output, content).map_err(|e| to_error(output, e.to_string()))?;
+    println!("{:?} converted to {:?}", input, output);
+    Ok(())
+}
+
+/// Prints effective playlist settings, each one with document which defines it
+pub fn explain(node_config: &NodeConfig) -> Result<()> {
+    for setting in Playlist::explain(node_config)? {
+        println!(
+            "{} = {}  # {}",
+            setting.key,
+            format_value(&setting.value),
+            setting.origin.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Returns value in single-line TOML notation
+fn format_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(table) => {
+            let fields: Vec<String> = table
+                .iter()
+                .map(|(k, v)| {
+                    let bare = k
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                    match bare {
+                        true => format!("{} = {}", k, format_value(v)),
+                        false => format!("{:?} = {}", k, format_value(v)),
+                    }
+                })
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        toml::Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(format_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Prints what would be played at each schedule change in given period
+pub fn simulate(
+    node_config: &NodeConfig,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+) -> Result<()> {
+    let playlist = Playlist::read_from_config(node_config)?;
+    let from = from.unwrap_or_else(|| {
+        let now = Local::now().naive_local();
+        now.with_nanosecond(0).unwrap_or(now)
+    });
+    let to = to.unwrap_or(from + Duration::days(1));
+
+    let mut dt = from;
+    while let Some(next_dt) = playlist.get_next_schedule_datetime(dt) {
+        if next_dt > to {
+            break;
+        }
+        for line in describe_schedule_change(&playlist, node_config, dt, next_dt) {
+            println!("{}  {}", next_dt.format("%Y-%m-%d %H:%M:%S"), line);
+        }
+        dt = next_dt;
+    }
+    Ok(())
+}
+
+/// Returns descriptions of content started at given datetime, previous datetime is used to detect changes
+fn describe_schedule_change(
+    playlist: &Playlist,
+    node_config: &NodeConfig,
+    prev_dt: NaiveDateTime,
+    dt: NaiveDateTime,
+) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let working_time = playlist.is_working_time(dt);
+    let just_before = dt - Duration::seconds(1);
+
+    if working_time != playlist.is_working_time(just_before) {
+        if working_time {
+            let folders = playlist.get_music_folders_for_date(dt.date());
+            lines.push(format!("working hours begin, music {:?}", folders));
+        } else {
+            lines.push("working hours end".to_string());
+        }
+    }
+
+    if let Some(file) = playlist.get_working_hours_announcement_file_path(prev_dt, dt) {
+        lines.push(format!("working hours announcement {:?}", file));
+    }
+
+    for occurrence in playlist.get_events_for_datetime(dt, &node_config.node) {
+        if occurrence.datetime == dt {
+            lines.push(format!(
+                "event {} with priority {}",
+                occurrence.id,
+                occurrence.priority()
+            ));
+        }
+    }
+
+    // advertizement and time announcement interrupt music only
+    if working_time && dt.second() == 0 {
+        let advertizement_folders = playlist.get_advertizement_folders_for_datetime(dt);
+        if !advertizement_folders.is_empty() {
+            lines.push(format!("advertizement block {:?}", advertizement_folders));
+        }
+        if let Some(file) = playlist.get_announcement_file_path_for_time(dt) {
+            lines.push(format!("time announcement {:?}", file));
+        }
+    }
+
+    let volume = playlist.get_volume(dt, ContentType::Music);
+    if volume != playlist.get_volume(just_before, ContentType::Music) {
+        lines.push(format!("music volume {}", volume));
+    }
+
+    lines
+}
+
+/// Prints media folders and files referenced by playlist with media files count, missing,
+/// empty and quarantined files, fails if any problem is found
+pub fn scan_library(node_config: &NodeConfig) -> Result<()> {
+    let playlist = Playlist::read_from_config(node_config)?;
+    let quarantine = Quarantine::load(node_config);
+
+    let (folders, files) = manifest::get_library_references(&playlist, node_config);
+
+    let media_folder = Path::new(&node_config.media.folder);
+    let mut problems = 0;
+    let file_problem = |path: &Path| match fs::metadata(path) {
+        Err(_) => Some("missing"),
+        Ok(m) if m.len() == 0 => Some("zero-length"),
+        Ok(_) if quarantine.is_quarantined(path) => Some("in quarantine"),
+        Ok(_) => None,
+    };
+
+    for folder in folders.iter() {
+        let path = media_folder.join(folder);
+        if !path.is_dir() {
+            println!("{}: missing", folder);
+            problems += 1;
+            continue;
+        }
+        let media_files = player::load_media_files_list_from_dir(&path);
+        let mut playable = 0;
+        for file in media_files.iter() {
+            match file_problem(file) {
+                Some(problem) => {
+                    let name = file.strip_prefix(media_folder).unwrap_or(file);
+                    println!("{:?}: {}", name, problem);
+                    problems += 1;
+                }
+                None => playable += 1,
+            }
+        }
+        println!(
+            "{}: {} media files, {} playable",
+            folder,
+            media_files.len(),
+            playable
+        );
+        if playable == 0 {
+            problems += 1;
+        }
+    }
+    for file in files.iter() {
+        match file_problem(&media_folder.join(file)) {
+            Some(problem) => {
+                println!("{}: {}", file, problem);
+                problems += 1;
+            }
+            None => println!("{}: ok", file),
+        }
+    }
+
+    println!("{} problems found", problems);
+    if problems > 0 {
+        return Err(Error::LibraryInvalid(problems));
+    }
+    Ok(())
+}
+
+/// Prints status reported by running node over control channel
+pub fn status(node_config: &NodeConfig) -> Result<()> {
+    let address = node_config
+        .control
+        .as_ref()
+        .map(|c| c.address.clone())
+        .ok_or_else(|| Error::Control("control.address is not defined".to_string()))?;
+    let control_error = |e: std::io::Error| Error::Control(format!("{}: {}", address, e));
+
+    let mut stream = TcpStream::connect(&address).map_err(control_error)?;
+    writeln!(stream, "status").map_err(control_error)?;
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .map_err(control_error)?;
+    println!("{}", response.trim_end());
+    Ok(())
+}
+
+/// Runs single media synchronization round and prints its result
+pub fn sync(node_config: &NodeConfig) -> Result<()> {
+    let syncer = Syncer::new(node_config)
+        .ok_or_else(|| Error::Sync("sync.url is not defined".to_string()))?;
+    let report = syncer.run()?;
+    println!(
+        "{} downloaded, {} deleted, {} unchanged, {} failed",
+        report.downloaded, report.deleted, report.unchanged, report.failed
+    );
+    Ok(())
+}
+
+/// Writes integrity manifest, referenced files and folders which are missing are printed
+pub fn manifest(node_config: &NodeConfig, output: Option<PathBuf>) -> Result<()> {
+    let playlist = Playlist::read_from_config(node_config)?;
+    let path = output.unwrap_or_else(|| manifest::get_manifest_path(node_config));
+    let write_error = |source| Error::ManifestWrite {
+        path: path.clone(),
+        source,
+    };
+
+    let (manifest, missing) = manifest::generate(&playlist, node_config).map_err(write_error)?;
+    for path in missing.iter() {
+        println!("{}: missing", path);
+    }
+    manifest.write_to_file(&path).map_err(write_error)?;
+    println!("{} files written to {:?}", manifest.files.len(), path);
+    Ok(())
+}
+
+/// Prints nodes advertised on LAN: name, version, host, addresses and control channel port
+pub fn discover(timeout: u64) -> Result<()> {
+    let nodes = discovery::discover(std::time::Duration::from_secs(timeout))?;
+    for node in nodes.iter() {
+        let control_port = node.control_port.map_or("-".to_string(), |p| p.to_string());
+        println!(
+            "{}  {}  {}  {}  control port {}",
+            node.name,
+            node.version,
+            node.host,
+            node.addresses.join(","),
+            control_port
+        );
+    }
+    println!("{} nodes found", nodes.len());
+    Ok(())
+}
+
+/// Rolls given or previous playlist version out to every node at once, version is checked before
+pub fn rollback(node_config: &NodeConfig, to: Option<String>) -> Result<()> {
+    let source = PlaylistSource::from_config(node_config);
+    let PlaylistSource::Folder(folder) = &source else {
+        return Err(Error::Rollout(
+            "playlist.url is defined, run rollback where playlist documents are stored".to_string(),
+        ));
+    };
+    let path = folder.join(rollout::ROLLOUT_DOCUMENT);
+    let current = Rollout::read(&source)?
+        .ok_or_else(|| Error::Rollout(format!("{:?} does not exist", path)))?;
+    let version = to
+        .or(current.previous.clone())
+        .ok_or_else(|| Error::Rollout("previous version is not defined".to_string()))?;
+
+    let (playlist, _) = Playlist::read_version(&source, &node_config.node, Some(&version))?;
+    let problems = playlist.validate();
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if !problems.is_empty() {
+        return Err(Error::PlaylistInvalid(problems.len()));
+    }
+
+    // rolled back version stays previous, so rollback can be undone the same way
+    let rollout = Rollout {
+        version: version.clone(),
+        previous: Some(current.version.clone()),
+        ..Default::default()
+    };
+    rollout.write_to_file(&path)?;
+    println!(
+        "playlist version {} is rolled back, version {} is rolled out to every node",
+        current.version, version
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn node_config(media: &TempDir) -> NodeConfig {
+        let config = format!(
+            "[media]\nfolder = {:?}\n[node]\nname = \"pc101\"",
+            media.path().to_string_lossy()
+        );
+        toml::from_str(&config).unwrap()
+    }
+
+    fn read_rollout(node_config: &NodeConfig) -> Rollout {
+        let source = PlaylistSource::from_config(node_config);
+        Rollout::read(&source).unwrap().unwrap()
+    }
+
+    #[test]
+    fn library_problems_fail_scan() {
+        let media = TempDir::new().unwrap();
+        let cfg = media.path().join("cfg");
+        fs::create_dir_all(&cfg).unwrap();
+        fs::write(
+            cfg.join("playlist.toml"),
+            "[music]\nschedule = [[2025-01-01, 2025-12-31, ['music']]]",
+        )
+        .unwrap();
+        let node_config = node_config(&media);
+
+        let result = scan_library(&node_config);
+        assert!(
+            matches!(result, Err(Error::LibraryInvalid(1))),
+            "{:?}",
+            result
+        );
+
+        fs::create_dir_all(media.path().join("music")).unwrap();
+        fs::write(media.path().join("music/track.mp3"), b"track").unwrap();
+        scan_library(&node_config).unwrap();
+    }
+
+    #[test]
+    fn rollbacks_can_follow_each_other() {
+        let media = TempDir::new().unwrap();
+        let cfg = media.path().join("cfg");
+        for version in ["1", "2"] {
+            let folder = cfg.join("versions").join(version);
+            fs::create_dir_all(&folder).unwrap();
+            fs::write(folder.join("playlist.toml"), "[music]\nshuffle = false").unwrap();
+        }
+        let rollout = Rollout {
+            version: "2".to_string(),
+            previous: Some("1".to_string()),
+            ..Default::default()
+        };
+        rollout
+            .write_to_file(&cfg.join(rollout::ROLLOUT_DOCUMENT))
+            .unwrap();
+        let node_config = node_config(&media);
+
+        rollback(&node_config, None).unwrap();
+        let rollout = read_rollout(&node_config);
+        assert_eq!(rollout.version, "1");
+        assert_eq!(rollout.previous.as_deref(), Some("2"));
+
+        rollback(&node_config, None).unwrap();
+        let rollout = read_rollout(&node_config);
+        assert_eq!(rollout.version, "2");
+        assert_eq!(rollout.previous.as_deref(), Some("1"));
+    }
+}
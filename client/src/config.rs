@@ -1,43 +1,201 @@
+use crate::error::{Error, Result};
+use crate::format::{self, Format};
+use crate::schema::{self, UnknownField};
 use gethostname::gethostname;
+use log::LevelFilter;
+use schemars::JsonSchema;
 use serde_derive::Deserialize;
 use std::fs;
 use std::path::Path;
-use toml;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug)]
 pub struct NodeConfig {
     pub media: Media,
     pub node: Node,
+    pub tts: Option<Tts>,
+    pub control: Option<Control>,
+    pub dead_air: Option<DeadAir>,
+    pub logging: Option<Logging>,
+    pub metrics: Option<Metrics>,
+    pub heartbeat: Option<Heartbeat>,
+    pub sync: Option<MediaSync>,
+    pub integrity: Option<Integrity>,
+    pub discovery: Option<Discovery>,
+    pub playlist: Option<RemotePlaylist>,
+    /// Keys of config file which are not settings, reported by `validate`
+    #[serde(skip)]
+    pub unknown_fields: Vec<UnknownField>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug)]
 pub struct Media {
     pub folder: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug)]
 pub struct Node {
     pub name: Option<String>,
+    pub groups: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct Tts {
+    pub command: Vec<String>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct Control {
+    pub address: String,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct Metrics {
+    pub address: String,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct Heartbeat {
+    pub url: String,
+    pub interval: Option<u64>,
+    pub buffer: Option<usize>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct MediaSync {
+    pub url: String,
+    pub interval: Option<u64>,
+    pub peers: Option<SyncPeers>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct SyncPeers {
+    pub address: String,
+    pub site: Option<String>,
+    pub interface: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct Integrity {
+    pub quarantine: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct RemotePlaylist {
+    pub url: String,
+    pub interval: Option<u64>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct Discovery {
+    pub advertise: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct DeadAir {
+    pub timeout: u64,
+    pub fallback_folder: Option<String>,
+    pub silence: Option<u64>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct Logging {
+    #[schemars(with = "Option<String>")]
+    pub level: Option<LevelFilter>,
+    pub targets: Option<Vec<LogTarget>>,
+    pub format: Option<LogFormat>,
+    pub file: Option<String>,
+    pub rotation: Option<LogRotation>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    Stdout,
+    File,
+    Syslog,
+    Journald,
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct LogRotation {
+    pub size: Option<u64>,
+    pub interval: Option<LogRotationInterval>,
+    pub count: Option<u32>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotationInterval {
+    Hour,
+    Day,
+    Week,
+}
+
+/// Settings given in command line or environment, take precedence over config file
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub media_folder: Option<String>,
+    pub node_name: Option<String>,
+}
+
+impl Node {
+    /// Returns true if node belongs to given group or has given tag
+    pub fn is_member_of(&self, group: &str) -> bool {
+        let contains = |list: &Option<Vec<String>>| list.iter().flatten().any(|g| g == group);
+        contains(&self.groups) || contains(&self.tags)
+    }
 }
 
 impl NodeConfig {
-    pub fn read_from_file(file_name: &str) -> NodeConfig {
-        let config_file_content = fs::read_to_string(file_name)
-            .expect(&format!("Unable to read config file {}!", file_name));
-        let mut conf: NodeConfig = toml::from_str(&config_file_content)
-            .expect(&format!("Unable to parse config file {}!", file_name));
+    pub fn read_from_file(file_name: &str, overrides: &Overrides) -> Result<NodeConfig> {
+        let config_file_content =
+            fs::read_to_string(file_name).map_err(|source| Error::ConfigRead {
+                path: file_name.to_string(),
+                source,
+            })?;
+        let to_error = |source| Error::ConfigParse {
+            path: file_name.to_string(),
+            source,
+        };
+        // YAML and JSON settings are checked the same way as TOML ones
+        let config_file_content = match Format::from_path(Path::new(file_name)) {
+            Some(format @ (Format::Yaml | Format::Json)) => {
+                let document =
+                    format::parse(format, &config_file_content, schema::get_node_config_schema)
+                        .map_err(to_error)?;
+                toml::to_string(&document).map_err(|e| to_error(serde::de::Error::custom(e)))?
+            }
+            _ => config_file_content,
+        };
+        let (mut conf, unknown_fields): (NodeConfig, _) =
+            schema::from_str(&config_file_content, &schema::get_node_config_schema())
+                .map_err(to_error)?;
+        conf.unknown_fields = unknown_fields;
+
+        if let Some(media_folder) = overrides.media_folder.as_ref() {
+            conf.media.folder = media_folder.clone();
+        }
+        if let Some(node_name) = overrides.node_name.as_ref() {
+            conf.node.name = Some(node_name.clone());
+        }
 
         if conf.node.name.is_none() {
-            conf.node.name = match gethostname().into_string() {
-                Ok(s) => Some(s),
-                Err(_) => None,
-            };
+            conf.node.name = gethostname().into_string().ok();
         };
 
         if !Path::new(&conf.media.folder).exists() {
-            panic!("Media folder \"{}\" does not exists!", &conf.media.folder);
+            return Err(Error::MediaFolderNotFound(conf.media.folder));
         };
 
-        conf
+        Ok(conf)
     }
 }
@@ -0,0 +1,155 @@
+use crate::config::NodeConfig;
+use crate::player::Layer;
+use crate::quarantine::QuarantineEntry;
+use crate::rollout::{self, RolloutStatus};
+use crate::signal::Signal;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Commands received from local control channel
+#[derive(Debug, Clone)]
+pub enum ControlCommand {
+    Message(Layer, PathBuf),
+    Cancel,
+}
+
+/// Node status reported over control channel
+#[derive(Debug, Default)]
+pub struct NodeStatus {
+    pub state: String,
+    pub now_playing: Option<PathBuf>,
+    pub volume: i32,
+    pub quarantine: Vec<QuarantineEntry>,
+    pub playlist_hash: Option<String>,
+    pub rollout: Option<RolloutStatus>,
+    pub library: BTreeMap<String, usize>,
+    pub recent_errors: Vec<String>,
+}
+
+pub type SharedStatus = Arc<Mutex<NodeStatus>>;
+
+enum Request {
+    Command(ControlCommand),
+    Status,
+    Quarantine,
+}
+
+/// Starts local control channel listener (if configured), received commands are sent to player as signals
+pub fn start_server(node_config: &NodeConfig, status: SharedStatus, sender: Sender<Signal>) {
+    let Some(control) = node_config.control.as_ref() else {
+        return;
+    };
+    let address = control.address.clone();
+    let listener = match TcpListener::bind(&address) {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("cannot bind control channel to {}: {}", address, e);
+            return;
+        }
+    };
+    log::info!("control channel listens on {}", address);
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            let status = status.clone();
+            thread::spawn(move || handle_connection(stream, sender, status));
+        }
+    });
+}
+
+/// Serves line-based protocol: one command per line, each command is answered with "ok ..." or "error: ..."
+fn handle_connection(stream: TcpStream, sender: Sender<Signal>, status: SharedStatus) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match parse_request(line.trim()) {
+            Ok(Request::Command(command)) => {
+                if sender.send(Signal::Command(command)).is_err() {
+                    break;
+                }
+                "ok".to_string()
+            }
+            Ok(Request::Status) => match status.lock() {
+                Ok(s) => {
+                    let playlist = match s.rollout.as_ref() {
+                        Some(r) if r.rolled_back => format!(
+                            "{} (rolled back from {})",
+                            rollout::describe_version(r.active.as_deref()),
+                            rollout::describe_version(r.target.as_deref())
+                        ),
+                        Some(r) if r.applied => rollout::describe_version(r.active.as_deref()),
+                        _ => "none".to_string(),
+                    };
+                    format!(
+                        "ok state={} volume={} now_playing={:?} playlist={:?}",
+                        s.state,
+                        s.volume,
+                        s.now_playing.as_deref().unwrap_or(Path::new("")),
+                        playlist
+                    )
+                }
+                Err(_) => "error: status is unavailable".to_string(),
+            },
+            Ok(Request::Quarantine) => match status.lock() {
+                Ok(s) => {
+                    let files: Vec<String> = s
+                        .quarantine
+                        .iter()
+                        .map(|e| format!("{:?} since {} ({})", e.path, e.since, e.reason))
+                        .collect();
+                    format!(
+                        "ok {} files in quarantine: {}",
+                        files.len(),
+                        files.join("; ")
+                    )
+                }
+                Err(_) => "error: status is unavailable".to_string(),
+            },
+            Err(e) => format!("error: {}", e),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+fn parse_request(line: &str) -> Result<Request, String> {
+    let (command, argument) = match line.split_once(' ') {
+        Some((c, a)) => (c, a.trim()),
+        None => (line, ""),
+    };
+
+    match command {
+        "message" | "emergency" if argument.is_empty() => {
+            Err(format!("{} requires media file path", command))
+        }
+        "message" => Ok(Request::Command(ControlCommand::Message(
+            Layer::OperatorMessage,
+            PathBuf::from(argument),
+        ))),
+        "emergency" => Ok(Request::Command(ControlCommand::Message(
+            Layer::Emergency,
+            PathBuf::from(argument),
+        ))),
+        "cancel" => Ok(Request::Command(ControlCommand::Cancel)),
+        "status" => Ok(Request::Status),
+        "quarantine" => Ok(Request::Quarantine),
+        _ => Err(format!("unknown command {:?}", command)),
+    }
+}
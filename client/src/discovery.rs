@@ -0,0 +1,218 @@
+use crate::config::NodeConfig;
+use crate::error::{Error, Result};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// DNS-SD service type of player nodes
+const SERVICE_TYPE: &str = "_distributed-player._tcp.local.";
+
+/// DNS-SD service type of peer content servers
+const PEER_SERVICE_TYPE: &str = "_distributed-player-peer._tcp.local.";
+
+/// Service name length limit, default one (15) is shorter than service types
+const SERVICE_NAME_LEN_MAX: u8 = 30;
+
+/// Node found on LAN
+#[derive(Debug, Clone)]
+pub struct DiscoveredNode {
+    pub name: String,
+    pub version: String,
+    pub host: String,
+    pub addresses: Vec<String>,
+    pub control_port: Option<u16>,
+}
+
+/// Change of peer content servers from the same site
+#[derive(Debug)]
+pub enum PeerEvent {
+    Found { node: String, address: SocketAddr },
+    Lost { node: String },
+}
+
+/// Advertises node name, version and control channel port over mDNS (unless disabled),
+/// node without control channel is not advertised, advertisement lasts while returned daemon exists
+pub fn advertise(node_config: &NodeConfig) -> Option<ServiceDaemon> {
+    let enabled = node_config
+        .discovery
+        .as_ref()
+        .and_then(|d| d.advertise)
+        .unwrap_or(true);
+    if !enabled {
+        return None;
+    }
+    let node_name = node_config.node.name.clone().unwrap_or_default();
+    let Some(control_port) = node_config
+        .control
+        .as_ref()
+        .and_then(|c| c.address.parse::<SocketAddr>().ok())
+        .map(|a| a.port())
+    else {
+        log::info!("node is not advertised over mDNS, control channel is not configured");
+        return None;
+    };
+
+    let properties = [
+        ("node", node_name.clone()),
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("control_port", control_port.to_string()),
+    ];
+    let service = new_daemon(None).and_then(|daemon| {
+        register(&daemon, SERVICE_TYPE, &node_name, control_port, &properties)?;
+        Ok(daemon)
+    });
+    match service {
+        Ok(daemon) => {
+            log::info!("node is advertised over mDNS as {}", node_name);
+            Some(daemon)
+        }
+        Err(e) => {
+            log::error!("cannot advertise node over mDNS: {}", e);
+            None
+        }
+    }
+}
+
+/// Advertises peer content server of node and reports content servers of other nodes
+/// from the same site, discovery uses only given network interface address if defined
+pub fn discover_peers(
+    node_name: &str,
+    site: &str,
+    port: u16,
+    interface: Option<&str>,
+    on_event: impl Fn(PeerEvent) + Send + 'static,
+) -> Result<ServiceDaemon> {
+    let discovery_error = |e: mdns_sd::Error| Error::Discovery(e.to_string());
+    let interface = match interface {
+        Some(address) => Some(
+            address
+                .parse::<IpAddr>()
+                .map_err(|e| Error::Discovery(format!("interface {}: {}", address, e)))?,
+        ),
+        None => None,
+    };
+    let daemon = new_daemon(interface).map_err(discovery_error)?;
+    let properties = [("node", node_name.to_string()), ("site", site.to_string())];
+    register(&daemon, PEER_SERVICE_TYPE, node_name, port, &properties).map_err(discovery_error)?;
+    let receiver = daemon.browse(PEER_SERVICE_TYPE).map_err(discovery_error)?;
+
+    let (node_name, site) = (node_name.to_string(), site.to_string());
+    thread::spawn(move || {
+        while let Ok(event) = receiver.recv() {
+            let event = match event {
+                ServiceEvent::ServiceResolved(info) => {
+                    let node = info.get_property_val_str("node").unwrap_or_default();
+                    let peer_site = info.get_property_val_str("site").unwrap_or_default();
+                    if node == node_name || peer_site != site {
+                        continue;
+                    }
+                    let addresses = info.get_addresses();
+                    let Some(ip) = addresses
+                        .iter()
+                        .find(|ip| ip.is_ipv4())
+                        .or(addresses.iter().next())
+                    else {
+                        continue;
+                    };
+                    PeerEvent::Found {
+                        node: node.to_string(),
+                        address: SocketAddr::new(*ip, info.get_port()),
+                    }
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => PeerEvent::Lost {
+                    node: fullname
+                        .strip_suffix(PEER_SERVICE_TYPE)
+                        .unwrap_or(&fullname)
+                        .trim_end_matches('.')
+                        .to_string(),
+                },
+                _ => continue,
+            };
+            on_event(event);
+        }
+    });
+    Ok(daemon)
+}
+
+/// Returns mDNS daemon, bound to given network interface address only if defined
+fn new_daemon(interface: Option<IpAddr>) -> mdns_sd::Result<ServiceDaemon> {
+    let daemon = ServiceDaemon::new()?;
+    daemon.set_service_name_len_max(SERVICE_NAME_LEN_MAX)?;
+    if let Some(address) = interface {
+        daemon.disable_interface(IfKind::All)?;
+        daemon.enable_interface(IfKind::Addr(address))?;
+    }
+    Ok(daemon)
+}
+
+/// Registers service of node with addresses of enabled network interfaces
+fn register(
+    daemon: &ServiceDaemon,
+    service_type: &str,
+    node_name: &str,
+    port: u16,
+    properties: &[(&str, String)],
+) -> mdns_sd::Result<()> {
+    // host name must be a valid DNS label
+    let host_name: String = node_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let info = ServiceInfo::new(
+        service_type,
+        node_name,
+        &format!("{}.local.", host_name),
+        "",
+        port,
+        properties,
+    )?
+    .enable_addr_auto();
+    daemon.register(info)
+}
+
+/// Returns nodes which answered mDNS queries during given time, sorted by name
+pub fn discover(timeout: Duration) -> Result<Vec<DiscoveredNode>> {
+    let daemon = ServiceDaemon::new().map_err(|e| Error::Discovery(e.to_string()))?;
+    let receiver = daemon
+        .browse(SERVICE_TYPE)
+        .map_err(|e| Error::Discovery(e.to_string()))?;
+
+    let mut nodes: BTreeMap<String, DiscoveredNode> = BTreeMap::new();
+    let deadline = Instant::now() + timeout;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = receiver.recv_timeout(left) else {
+            break;
+        };
+        if let ServiceEvent::ServiceResolved(info) = event {
+            let mut addresses: Vec<String> =
+                info.get_addresses().iter().map(|a| a.to_string()).collect();
+            addresses.sort();
+            let name = info
+                .get_property_val_str("node")
+                .unwrap_or(info.get_fullname())
+                .to_string();
+            nodes.insert(
+                info.get_fullname().to_string(),
+                DiscoveredNode {
+                    name,
+                    version: info
+                        .get_property_val_str("version")
+                        .unwrap_or_default()
+                        .to_string(),
+                    host: info.get_hostname().to_string(),
+                    addresses,
+                    control_port: info
+                        .get_property_val_str("control_port")
+                        .and_then(|p| p.parse().ok()),
+                },
+            );
+        }
+    }
+    let _ = daemon.shutdown();
+
+    let mut nodes: Vec<DiscoveredNode> = nodes.into_values().collect();
+    nodes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(nodes)
+}
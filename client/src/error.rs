@@ -0,0 +1,124 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("unable to read config file {path}: {source}")]
+    ConfigRead { path: String, source: io::Error },
+
+    #[error("unable to parse config file {path}: {source}")]
+    ConfigParse {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    #[error("node config has {0} problems")]
+    ConfigInvalid(usize),
+
+    #[error("unable to write schema {path:?}: {source}")]
+    SchemaWrite { path: PathBuf, source: io::Error },
+
+    #[error("unable to convert {path:?}: {reason}")]
+    Convert { path: PathBuf, reason: String },
+
+    #[error("media folder \"{0}\" does not exist")]
+    MediaFolderNotFound(String),
+
+    #[error("node name is not defined and host name is not available")]
+    NodeNameUnavailable,
+
+    #[error("unable to configure logging: {0}")]
+    Logging(String),
+
+    #[error("unable to read playlist file {path:?}: {source}")]
+    PlaylistRead { path: PathBuf, source: io::Error },
+
+    #[error("unable to parse playlist file {path:?}: {source}")]
+    PlaylistParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[error("unable to fetch playlist {url}: {reason}")]
+    PlaylistFetch { url: String, reason: String },
+
+    #[error("playlist has {0} problems")]
+    PlaylistInvalid(usize),
+
+    #[error("playlist rollout failure: {0}")]
+    Rollout(String),
+
+    #[error("unable to open media file {0:?}")]
+    MediaOpen(PathBuf),
+
+    #[error("unable to play media file {path:?}: {reason}")]
+    MediaPlay { path: PathBuf, reason: String },
+
+    #[error("media library has {0} problems")]
+    LibraryInvalid(usize),
+
+    #[error("playback of media file {path:?} stalled: {reason}")]
+    MediaStalled { path: PathBuf, reason: String },
+
+    #[error("media file {0:?} is in quarantine")]
+    MediaQuarantined(PathBuf),
+
+    #[error("media file {path:?} does not match integrity manifest: {reason}")]
+    MediaIntegrity { path: PathBuf, reason: String },
+
+    #[error("libvlc failure: {0}")]
+    Backend(String),
+
+    #[error("control channel failure: {0}")]
+    Control(String),
+
+    #[error("media sync failure: {0}")]
+    Sync(String),
+
+    #[error("unable to write manifest {path:?}: {source}")]
+    ManifestWrite { path: PathBuf, source: io::Error },
+
+    #[error("node discovery failure: {0}")]
+    Discovery(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Failure category: config, playlist, media, backend, control or sync
+    pub fn category(&self) -> &'static str {
+        match self {
+            Error::ConfigRead { .. }
+            | Error::ConfigParse { .. }
+            | Error::ConfigInvalid(_)
+            | Error::SchemaWrite { .. }
+            | Error::Convert { .. }
+            | Error::MediaFolderNotFound(_)
+            | Error::NodeNameUnavailable
+            | Error::Logging(_) => "config",
+            Error::PlaylistRead { .. }
+            | Error::PlaylistParse { .. }
+            | Error::PlaylistFetch { .. }
+            | Error::PlaylistInvalid(_)
+            | Error::Rollout(_) => "playlist",
+            Error::LibraryInvalid(_)
+            | Error::MediaOpen(_)
+            | Error::MediaPlay { .. }
+            | Error::MediaStalled { .. }
+            | Error::MediaQuarantined(_)
+            | Error::MediaIntegrity { .. } => "media",
+            Error::Backend(_) => "backend",
+            Error::Control(_) | Error::Discovery(_) => "control",
+            Error::Sync(_) | Error::ManifestWrite { .. } => "sync",
+        }
+    }
+
+    /// Writes error to log, category is used as log target so each category gets its own log event
+    pub fn log(&self) {
+        log::error!(
+            target: self.category(), category = self.category();
+            "{} error: {}", self.category(), self
+        );
+    }
+}
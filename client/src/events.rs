@@ -0,0 +1,85 @@
+use crate::config::NodeConfig;
+use crate::playlist::EventOccurrence;
+use chrono::{prelude::*, Duration};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Journal entries older than this limit are removed on load
+const JOURNAL_RETENTION_DAYS: i64 = 2;
+
+/// Per-node journal of played events occurrences, survives node restarts
+pub struct EventsJournal {
+    file_path: PathBuf,
+    played: HashSet<(NaiveDateTime, String)>,
+}
+
+impl EventsJournal {
+    pub fn load(node_config: &NodeConfig) -> EventsJournal {
+        let media_folder = &node_config.media.folder;
+        let node_name = node_config.node.name.clone().unwrap_or_default();
+        let file_path = Path::new(media_folder)
+            .join("state")
+            .join(format!("events_{}.txt", node_name));
+
+        let oldest_dt = Local::now().naive_local() - Duration::days(JOURNAL_RETENTION_DAYS);
+        let mut played = HashSet::new();
+        if let Ok(content) = fs::read_to_string(&file_path) {
+            for line in content.lines() {
+                if let Some((dt, id)) = line.split_once(' ') {
+                    if let Ok(dt) = dt.parse::<NaiveDateTime>() {
+                        if dt >= oldest_dt {
+                            played.insert((dt, id.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+
+        let journal = EventsJournal { file_path, played };
+        journal.save();
+        journal
+    }
+
+    pub fn is_played(&self, occurrence: &EventOccurrence) -> bool {
+        self.played
+            .contains(&(occurrence.datetime, occurrence.id.clone()))
+    }
+
+    pub fn mark_played(&mut self, occurrence: &EventOccurrence) {
+        self.played
+            .insert((occurrence.datetime, occurrence.id.clone()));
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path);
+        match file {
+            Ok(mut f) => {
+                if let Err(e) = writeln!(f, "{} {}", format_dt(occurrence.datetime), occurrence.id)
+                {
+                    log::error!("cannot write events journal {:?}: {}", self.file_path, e);
+                }
+            }
+            Err(e) => log::error!("cannot open events journal {:?}: {}", self.file_path, e),
+        }
+    }
+
+    fn save(&self) {
+        if let Some(parent) = self.file_path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let mut content = String::new();
+        for (dt, id) in self.played.iter() {
+            content.push_str(&format!("{} {}\n", format_dt(*dt), id));
+        }
+        if let Err(e) = fs::write(&self.file_path, content) {
+            log::error!("cannot write events journal {:?}: {}", self.file_path, e);
+        }
+    }
+}
+
+fn format_dt(dt: NaiveDateTime) -> String {
+    dt.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
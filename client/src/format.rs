@@ -0,0 +1,190 @@
+use crate::schema;
+use serde_json::Value as JsonValue;
+use std::path::Path;
+
+/// Settings document format, chosen by file extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+/// Extensions of supported formats in lookup order
+pub const EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Parses document into TOML value, so documents of every format are merged and validated
+/// the same way; YAML and JSON strings become TOML dates and times where schema expects them
+pub fn parse(
+    format: Format,
+    content: &str,
+    schema: impl FnOnce() -> JsonValue,
+) -> Result<toml::Value, toml::de::Error> {
+    let document: JsonValue = match format {
+        Format::Toml => return toml::from_str(content),
+        Format::Yaml => serde_yaml::from_str(content).map_err(serde::de::Error::custom)?,
+        Format::Json => serde_json::from_str(content).map_err(serde::de::Error::custom)?,
+    };
+    let mut value = match from_json(document) {
+        Some(value @ toml::Value::Table(_)) => value,
+        // empty YAML document
+        None => toml::Value::Table(toml::value::Table::new()),
+        Some(_) => {
+            return Err(serde::de::Error::custom(
+                "document must be a table of settings",
+            ))
+        }
+    };
+    schema::parse_datetimes(&mut value, &schema());
+    Ok(value)
+}
+
+/// Formats document, TOML dates and times are written as strings to YAML and JSON
+pub fn format(format: Format, value: &toml::Value) -> Result<String, String> {
+    match format {
+        Format::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::to_string(&to_json(value)).map_err(|e| e.to_string()),
+        Format::Json => serde_json::to_string_pretty(&to_json(value))
+            .map(|content| content + "\n")
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Converts JSON value to TOML one, nulls are dropped as TOML has no null
+fn from_json(value: JsonValue) -> Option<toml::Value> {
+    Some(match value {
+        JsonValue::Null => return None,
+        JsonValue::Bool(b) => toml::Value::Boolean(b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64()?),
+        },
+        JsonValue::String(s) => toml::Value::String(s),
+        JsonValue::Array(items) => {
+            toml::Value::Array(items.into_iter().filter_map(from_json).collect())
+        }
+        JsonValue::Object(fields) => toml::Value::Table(
+            fields
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, from_json(value)?)))
+                .collect(),
+        ),
+    })
+}
+
+fn to_json(value: &toml::Value) -> JsonValue {
+    match value {
+        toml::Value::String(s) => JsonValue::String(s.clone()),
+        toml::Value::Integer(i) => JsonValue::from(*i),
+        toml::Value::Float(f) => JsonValue::from(*f),
+        toml::Value::Boolean(b) => JsonValue::Bool(*b),
+        toml::Value::Datetime(datetime) => JsonValue::String(datetime.to_string()),
+        toml::Value::Array(items) => JsonValue::Array(items.iter().map(to_json).collect()),
+        toml::Value::Table(fields) => JsonValue::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::get_playlist_schema;
+
+    const TOML_PLAYLIST: &str = r#"
+        [working_hours]
+        schedule = [[09:00:00, 21:00:00]]
+        [working_hours.exceptions]
+        2025-01-01 = [12:00:00, 18:00:00]
+        [music]
+        schedule = [[2025-01-01, 2025-12-31, ["music/2025-01-01"]]]
+        [advertizement.schedule]
+        "00:10:00" = ["ads/00:10:00"]
+        [events.sale]
+        at = 2025-03-01T12:00:00
+        text = "Sale starts at 12:00:00"
+        [events.sale_end]
+        time = 20:00:00
+        file = "2025-03-01"
+        _replace = true
+        [volume]
+        schedule = [{ from = 09:00:00, to = 12:00:00, level = 60 }]
+    "#;
+
+    const YAML_PLAYLIST: &str = r#"
+        working_hours:
+          schedule: [["09:00:00", "21:00:00"]]
+          exceptions:
+            "2025-01-01": ["12:00:00", "18:00:00"]
+        music:
+          schedule: [["2025-01-01", "2025-12-31", ["music/2025-01-01"]]]
+        advertizement:
+          schedule:
+            "00:10:00": ["ads/00:10:00"]
+        events:
+          sale:
+            at: "2025-03-01T12:00:00"
+            text: "Sale starts at 12:00:00"
+          sale_end:
+            time: "20:00:00"
+            file: "2025-03-01"
+            _replace: true
+        volume:
+          schedule: [{ from: "09:00:00", to: "12:00:00", level: 60 }]
+        fade: ~
+    "#;
+
+    fn parse_playlist(format: Format, content: &str) -> toml::Value {
+        parse(format, content, get_playlist_schema).unwrap()
+    }
+
+    #[test]
+    fn dates_and_times_keep_their_meaning_in_every_format() {
+        let expected = parse_playlist(Format::Toml, TOML_PLAYLIST);
+        assert_eq!(parse_playlist(Format::Yaml, YAML_PLAYLIST), expected);
+
+        let json = serde_json::to_string(&to_json(&expected)).unwrap();
+        assert_eq!(parse_playlist(Format::Json, &json), expected);
+    }
+
+    #[test]
+    fn strings_which_look_like_dates_stay_strings() {
+        let value = parse_playlist(Format::Yaml, YAML_PLAYLIST);
+        let sale_end = &value["events"]["sale_end"];
+        assert_eq!(sale_end["file"].as_str(), Some("2025-03-01"));
+        assert!(sale_end["time"].is_datetime());
+        assert_eq!(
+            value["events"]["sale"]["text"].as_str(),
+            Some("Sale starts at 12:00:00")
+        );
+    }
+
+    #[test]
+    fn documents_are_converted_without_changes() {
+        let value = parse_playlist(Format::Toml, TOML_PLAYLIST);
+        for target in [Format::Toml, Format::Yaml, Format::Json] {
+            let content = format(target, &value).unwrap();
+            assert_eq!(parse_playlist(target, &content), value, "{:?}", target);
+        }
+    }
+
+    #[test]
+    fn document_must_be_table() {
+        assert!(parse(Format::Json, "[1, 2]", get_playlist_schema).is_err());
+        assert!(parse(Format::Yaml, "", get_playlist_schema).is_ok());
+    }
+}
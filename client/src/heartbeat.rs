@@ -0,0 +1,135 @@
+use crate::config::NodeConfig;
+use crate::control::SharedStatus;
+use chrono::prelude::*;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Heartbeat interval if not defined in node settings, in seconds
+const DEFAULT_HEARTBEAT_INTERVAL: u64 = 60;
+
+/// Max count of heartbeats kept while collector is unreachable, if not defined in node settings
+const DEFAULT_HEARTBEAT_BUFFER: usize = 1000;
+
+/// Collector request timeout
+const COLLECTOR_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Reporter {
+    url: String,
+    node_name: String,
+    media_folder: String,
+    buffer_path: PathBuf,
+    buffer_limit: usize,
+    started: Instant,
+    status: SharedStatus,
+}
+
+/// Starts periodic heartbeat sending to collector (if configured)
+pub fn start(node_config: &NodeConfig, status: SharedStatus) {
+    let Some(heartbeat) = node_config.heartbeat.as_ref() else {
+        return;
+    };
+    let node_name = node_config.node.name.clone().unwrap_or_default();
+    let interval = Duration::from_secs(heartbeat.interval.unwrap_or(DEFAULT_HEARTBEAT_INTERVAL));
+    let reporter = Reporter {
+        url: heartbeat.url.clone(),
+        buffer_path: Path::new(&node_config.media.folder)
+            .join("state")
+            .join(format!("heartbeats_{}.jsonl", node_name)),
+        node_name,
+        media_folder: node_config.media.folder.clone(),
+        buffer_limit: heartbeat.buffer.unwrap_or(DEFAULT_HEARTBEAT_BUFFER),
+        started: Instant::now(),
+        status,
+    };
+    log::info!(
+        "heartbeats are sent to {} every {:?}",
+        reporter.url,
+        interval
+    );
+
+    thread::spawn(move || loop {
+        reporter.report();
+        thread::sleep(interval);
+    });
+}
+
+impl Reporter {
+    fn report(&self) {
+        let mut queue = self.load_buffer();
+        queue.push(self.get_heartbeat());
+
+        // buffered heartbeats are sent first, the rest is kept after first failure
+        let mut sent = 0;
+        for heartbeat in queue.iter() {
+            if let Err(e) = ureq::post(&self.url)
+                .timeout(COLLECTOR_TIMEOUT)
+                .send_json(heartbeat)
+            {
+                log::warn!("cannot send heartbeat to {}: {}", self.url, e);
+                break;
+            }
+            sent += 1;
+        }
+        self.save_buffer(&queue[sent..]);
+    }
+
+    fn get_heartbeat(&self) -> Value {
+        let status = match self.status.lock() {
+            Ok(s) => json!({
+                "state": s.state,
+                "now_playing": s.now_playing,
+                "volume": s.volume,
+                "playlist_hash": s.playlist_hash,
+                "rollout": s.rollout,
+                "library": s.library,
+                "quarantine": s.quarantine.len(),
+                "recent_errors": s.recent_errors,
+            }),
+            Err(_) => json!({}),
+        };
+        json!({
+            "node": self.node_name,
+            "version": env!("CARGO_PKG_VERSION"),
+            "time": Local::now().format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+            "uptime": self.started.elapsed().as_secs(),
+            "free_disk_space": fs2::available_space(&self.media_folder).ok(),
+            "status": status,
+        })
+    }
+
+    fn load_buffer(&self) -> Vec<Value> {
+        let Ok(content) = fs::read_to_string(&self.buffer_path) else {
+            return Vec::new();
+        };
+        content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()
+    }
+
+    /// Replaces buffer with given heartbeats, the oldest ones are dropped over the limit
+    fn save_buffer(&self, heartbeats: &[Value]) {
+        if heartbeats.is_empty() {
+            let _ = fs::remove_file(&self.buffer_path);
+            return;
+        }
+        let skip = heartbeats.len().saturating_sub(self.buffer_limit);
+        let content: String = heartbeats[skip..]
+            .iter()
+            .map(|h| format!("{}\n", h))
+            .collect();
+        if let Some(parent) = self.buffer_path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::write(&self.buffer_path, content) {
+            log::error!(
+                "cannot write heartbeats buffer {:?}: {}",
+                self.buffer_path,
+                e
+            );
+        }
+    }
+}
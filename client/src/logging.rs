@@ -0,0 +1,326 @@
+use crate::config::{LogFormat, LogRotationInterval, LogTarget, Logging, NodeConfig};
+use crate::error::{Error, Result};
+use log::{LevelFilter, Record};
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::file::FileAppender;
+use log4rs::append::rolling_file::policy::compound::roll::delete::DeleteRoller;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::roll::Roll;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::trigger::time::{
+    TimeTrigger, TimeTriggerConfig, TimeTriggerInterval,
+};
+use log4rs::append::rolling_file::policy::compound::trigger::Trigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::append::Append;
+use log4rs::config::{Appender, Config, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::writer::simple::SimpleWriter;
+use log4rs::encode::Encode;
+use std::path::{Path, PathBuf};
+
+/// Line pattern for stdout and file targets
+const TEXT_PATTERN: &str = "{d(%Y-%m-%d %H:%M:%S)} {l}: {m}{n}";
+
+/// Message pattern for syslog and journald targets, they add time and level themselves
+const SYSTEM_LOG_PATTERN: &str = "{m}";
+
+/// Identifier of node messages in system log
+const SYSLOG_IDENTIFIER: &str = "distributed_player";
+
+/// Configures log targets from node settings, default is info level text file inside media folder
+pub fn configure_logger(node_config: &NodeConfig, level: Option<LevelFilter>) -> Result<()> {
+    let default_logging = Logging {
+        level: None,
+        targets: None,
+        format: None,
+        file: None,
+        rotation: None,
+    };
+    let logging = node_config.logging.as_ref().unwrap_or(&default_logging);
+    let level = level.or(logging.level).unwrap_or(LevelFilter::Info);
+    let format = logging.format.unwrap_or_default();
+    let targets = logging
+        .targets
+        .clone()
+        .unwrap_or_else(|| vec![LogTarget::File]);
+
+    let mut config = Config::builder();
+    let mut root = Root::builder();
+    for target in targets {
+        let name = format!("{:?}", target).to_lowercase();
+        let appender: Box<dyn Append> = match target {
+            LogTarget::Stdout => Box::new(
+                ConsoleAppender::builder()
+                    .encoder(get_encoder(format, TEXT_PATTERN))
+                    .build(),
+            ),
+            LogTarget::File => build_file_appender(node_config, logging, format)?,
+            LogTarget::Syslog => Box::new(system_log::Syslog::connect(get_encoder(
+                format,
+                SYSTEM_LOG_PATTERN,
+            ))?),
+            LogTarget::Journald => Box::new(system_log::Journald::connect(get_encoder(
+                format,
+                SYSTEM_LOG_PATTERN,
+            ))?),
+        };
+        config = config.appender(Appender::builder().build(&name, appender));
+        root = root.appender(&name);
+    }
+
+    let config = config
+        .build(root.build(level))
+        .map_err(|e| Error::Logging(e.to_string()))?;
+    log4rs::init_config(config).map_err(|e| Error::Logging(e.to_string()))?;
+    Ok(())
+}
+
+fn get_encoder(format: LogFormat, pattern: &str) -> Box<dyn Encode> {
+    match format {
+        LogFormat::Text => Box::new(PatternEncoder::new(pattern)),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    }
+}
+
+/// Returns log file path from settings or `logs/log_{node}.txt` inside media folder
+fn get_log_file_path(node_config: &NodeConfig, logging: &Logging) -> Result<PathBuf> {
+    let node_name = node_config
+        .node
+        .name
+        .as_ref()
+        .ok_or(Error::NodeNameUnavailable)?;
+    Ok(match logging.file.as_ref() {
+        Some(file) => PathBuf::from(file.replace("{node}", node_name)),
+        None => Path::new(&node_config.media.folder)
+            .join("logs")
+            .join(format!("log_{}.txt", node_name)),
+    })
+}
+
+fn build_file_appender(
+    node_config: &NodeConfig,
+    logging: &Logging,
+    format: LogFormat,
+) -> Result<Box<dyn Append>> {
+    let log_file_path = get_log_file_path(node_config, logging)?;
+    let file_error = |e: anyhow::Error| {
+        Error::Logging(format!(
+            "cannot access to log file {:?}: {}",
+            &log_file_path, e
+        ))
+    };
+
+    let Some(rotation) = logging.rotation.as_ref() else {
+        let appender = FileAppender::builder()
+            .encoder(get_encoder(format, TEXT_PATTERN))
+            .build(&log_file_path)
+            .map_err(|e| file_error(e.into()))?;
+        return Ok(Box::new(appender));
+    };
+
+    let trigger: Box<dyn Trigger> = match (rotation.size, rotation.interval) {
+        (Some(size), None) => Box::new(SizeTrigger::new(size)),
+        (None, Some(interval)) => Box::new(TimeTrigger::new(TimeTriggerConfig {
+            interval: match interval {
+                LogRotationInterval::Hour => TimeTriggerInterval::Hour(1),
+                LogRotationInterval::Day => TimeTriggerInterval::Day(1),
+                LogRotationInterval::Week => TimeTriggerInterval::Week(1),
+            },
+            modulate: true,
+            max_random_delay: 0,
+        })),
+        _ => {
+            return Err(Error::Logging(
+                "log rotation must define either size or interval".to_string(),
+            ))
+        }
+    };
+
+    // rotated files are kept as log.txt.1 ... log.txt.{count}
+    let count = rotation.count.unwrap_or(0);
+    let roller: Box<dyn Roll> = if count == 0 {
+        Box::new(DeleteRoller::new())
+    } else {
+        let pattern = format!("{}.{{}}", log_file_path.to_string_lossy());
+        Box::new(
+            FixedWindowRoller::builder()
+                .build(&pattern, count)
+                .map_err(file_error)?,
+        )
+    };
+
+    let appender = RollingFileAppender::builder()
+        .encoder(get_encoder(format, TEXT_PATTERN))
+        .build(
+            &log_file_path,
+            Box::new(CompoundPolicy::new(trigger, roller)),
+        )
+        .map_err(|e| file_error(e.into()))?;
+    Ok(Box::new(appender))
+}
+
+/// Encodes record with given encoder, trailing line break is removed
+fn encode_message(encoder: &dyn Encode, record: &Record) -> anyhow::Result<String> {
+    let mut buffer = SimpleWriter(Vec::new());
+    encoder.encode(&mut buffer, record)?;
+    Ok(String::from_utf8_lossy(&buffer.0).trim_end().to_string())
+}
+
+#[cfg(unix)]
+mod system_log {
+    use super::{encode_message, SYSLOG_IDENTIFIER};
+    use crate::error::{Error, Result};
+    use log::kv::{Key, Value, VisitSource};
+    use log::{Level, Record};
+    use log4rs::append::Append;
+    use log4rs::encode::Encode;
+    use std::os::unix::net::UnixDatagram;
+    use std::process;
+
+    const SYSLOG_SOCKET: &str = "/dev/log";
+    const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+    /// Syslog severity of log level
+    fn get_severity(level: Level) -> u8 {
+        match level {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        }
+    }
+
+    fn connect(path: &str) -> Result<UnixDatagram> {
+        let socket = UnixDatagram::unbound().map_err(|e| Error::Logging(e.to_string()))?;
+        socket
+            .connect(path)
+            .map_err(|e| Error::Logging(format!("cannot connect to {}: {}", path, e)))?;
+        Ok(socket)
+    }
+
+    /// Sends records to local syslog daemon (RFC 3164, user facility)
+    #[derive(Debug)]
+    pub struct Syslog {
+        socket: UnixDatagram,
+        encoder: Box<dyn Encode>,
+    }
+
+    impl Syslog {
+        pub fn connect(encoder: Box<dyn Encode>) -> Result<Syslog> {
+            Ok(Syslog {
+                socket: connect(SYSLOG_SOCKET)?,
+                encoder,
+            })
+        }
+    }
+
+    impl Append for Syslog {
+        fn append(&self, record: &Record) -> anyhow::Result<()> {
+            let priority = 8 + get_severity(record.level());
+            let message = encode_message(self.encoder.as_ref(), record)?;
+            let line = format!(
+                "<{}>{}[{}]: {}",
+                priority,
+                SYSLOG_IDENTIFIER,
+                process::id(),
+                message
+            );
+            self.socket.send(line.as_bytes())?;
+            Ok(())
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Sends records to systemd journal, record key-values are passed as journal fields
+    #[derive(Debug)]
+    pub struct Journald {
+        socket: UnixDatagram,
+        encoder: Box<dyn Encode>,
+    }
+
+    impl Journald {
+        pub fn connect(encoder: Box<dyn Encode>) -> Result<Journald> {
+            Ok(Journald {
+                socket: connect(JOURNALD_SOCKET)?,
+                encoder,
+            })
+        }
+    }
+
+    struct FieldsVisitor<'a>(&'a mut String);
+
+    impl<'kvs> VisitSource<'kvs> for FieldsVisitor<'_> {
+        fn visit_pair(
+            &mut self,
+            key: Key<'kvs>,
+            value: Value<'kvs>,
+        ) -> std::result::Result<(), log::kv::Error> {
+            add_field(self.0, &key.as_str().to_uppercase(), &value.to_string());
+            Ok(())
+        }
+    }
+
+    /// Adds field in journal native protocol, values are single-line
+    fn add_field(datagram: &mut String, name: &str, value: &str) {
+        datagram.push_str(name);
+        datagram.push('=');
+        datagram.push_str(&value.replace('\n', " "));
+        datagram.push('\n');
+    }
+
+    impl Append for Journald {
+        fn append(&self, record: &Record) -> anyhow::Result<()> {
+            let mut datagram = String::new();
+            let message = encode_message(self.encoder.as_ref(), record)?;
+            add_field(&mut datagram, "MESSAGE", &message);
+            add_field(
+                &mut datagram,
+                "PRIORITY",
+                &get_severity(record.level()).to_string(),
+            );
+            add_field(&mut datagram, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+            add_field(&mut datagram, "TARGET", record.target());
+            record
+                .key_values()
+                .visit(&mut FieldsVisitor(&mut datagram))?;
+            self.socket.send(datagram.as_bytes())?;
+            Ok(())
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(not(unix))]
+mod system_log {
+    use crate::error::{Error, Result};
+    use log::Record;
+    use log4rs::append::Append;
+    use log4rs::encode::Encode;
+
+    /// System log targets are available on unix hosts only
+    #[derive(Debug)]
+    pub struct Syslog;
+
+    impl Syslog {
+        pub fn connect(_: Box<dyn Encode>) -> Result<Syslog> {
+            Err(Error::Logging(
+                "syslog is not supported on this host".to_string(),
+            ))
+        }
+    }
+
+    impl Append for Syslog {
+        fn append(&self, _: &Record) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn flush(&self) {}
+    }
+
+    pub type Journald = Syslog;
+}
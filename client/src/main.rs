@@ -1,39 +1,87 @@
-use std::path::Path;
+use std::process;
 
-use config::NodeConfig;
-use log::LevelFilter;
-use log4rs::append::file::FileAppender;
-use log4rs::config::{Appender, Config, Root};
-use log4rs::encode::pattern::PatternEncoder;
+use clap::Parser;
+use cli::{Cli, Command};
+use error::Result;
 
+mod cli;
+mod commands;
 mod config;
+mod control;
+mod discovery;
+mod error;
+mod events;
+mod format;
+mod heartbeat;
+mod logging;
+mod manifest;
+mod merge;
+mod metrics;
+mod peers;
 mod player;
 mod playlist;
+mod playlist_source;
+mod quarantine;
+mod rollout;
+mod schema;
+mod signal;
+mod silence;
+mod state;
+mod sync;
+#[cfg(test)]
+mod test_util;
+mod tts;
 
 fn main() {
-    let conf = config::NodeConfig::read_from_file("node_config.toml");
-    configure_logger(&conf);
-    player::Player::new(&conf).start();
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        e.log();
+        process::exit(1);
+    }
 }
 
-fn configure_logger(node_config: &NodeConfig) {
-    let media_folder = &node_config.media.folder;
-    let node_name = node_config.node.name.as_ref();
-    let log_file_path = Path::new(media_folder)
-        .join("logs")
-        .join(format!("log_{}.txt", node_name.unwrap()));
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    // schema and conversion do not depend on node settings
+    match cli.command.as_ref() {
+        Some(Command::Schema { kind, output }) => {
+            return commands::schema(*kind, output.as_deref())
+        }
+        Some(Command::Convert {
+            input,
+            output,
+            kind,
+        }) => return commands::convert(*kind, input, output),
+        _ => {}
+    }
+    let overrides = config::Overrides {
+        media_folder: cli.media_folder,
+        node_name: cli.node_name,
+    };
+    let conf = config::NodeConfig::read_from_file(&cli.config.to_string_lossy(), &overrides)?;
 
-    let logfile = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%Y-%m-%d %H:%M:%S)} {l}: {m}{n}",
-        )))
-        .build(&log_file_path)
-        .expect(&format!("Cannot access to log file {:?}!", &log_file_path));
-
-    let config = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .build(Root::builder().appender("logfile").build(LevelFilter::Info))
-        .unwrap();
-
-    log4rs::init_config(config).expect("Cannot configure logging framework!");
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => {
+            logging::configure_logger(&conf, cli.log_level)?;
+            for field in conf.unknown_fields.iter() {
+                log::warn!("{:?}: {}", cli.config, field);
+            }
+            let _advertisement = discovery::advertise(&conf);
+            player::Player::new(&conf)?.start();
+        }
+        Command::Validate => commands::validate(&conf)?,
+        Command::Explain => commands::explain(&conf)?,
+        Command::Simulate { from, to } => commands::simulate(&conf, from, to)?,
+        Command::ScanLibrary => commands::scan_library(&conf)?,
+        Command::Status => commands::status(&conf)?,
+        Command::Sync => {
+            logging::configure_logger(&conf, cli.log_level)?;
+            commands::sync(&conf)?
+        }
+        Command::Manifest { output } => commands::manifest(&conf, output)?,
+        Command::Discover { timeout } => commands::discover(timeout)?,
+        Command::Rollback { to } => commands::rollback(&conf, to)?,
+        Command::Schema { .. } | Command::Convert { .. } => {}
+    }
+    Ok(())
 }
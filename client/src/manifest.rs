@@ -0,0 +1,248 @@
+use crate::config::NodeConfig;
+use crate::playlist::Playlist;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Media file description, path is relative to media folder with unix style slashes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    #[serde(deserialize_with = "deserialize_sha256")]
+    pub sha256: String,
+}
+
+/// Returns true if hash is 64 lowercase hex digits, hash is used in file names and URLs
+pub fn is_valid_sha256(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn deserialize_sha256<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let hash: String = serde::Deserialize::deserialize(deserializer)?;
+    match is_valid_sha256(&hash) {
+        true => Ok(hash),
+        false => Err(serde::de::Error::custom(format!(
+            "invalid SHA-256 {:?}",
+            hash
+        ))),
+    }
+}
+
+/// List of media files with their sizes and hashes
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn read_from_file(path: &Path) -> io::Result<Manifest> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes manifest at once, so readers never see half-written file
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// Returns path of integrity manifest inside media folder
+pub fn get_manifest_path(node_config: &NodeConfig) -> PathBuf {
+    Path::new(&node_config.media.folder)
+        .join("cfg")
+        .join("manifest.json")
+}
+
+/// Returns media folders and files used by node: referenced by playlist and dead air fallback folder
+pub fn get_library_references(
+    playlist: &Playlist,
+    node_config: &NodeConfig,
+) -> (Vec<String>, Vec<String>) {
+    let (mut folders, files) = playlist.get_media_references();
+    if let Some(folder) = node_config
+        .dead_air
+        .as_ref()
+        .and_then(|d| d.fallback_folder.as_ref())
+    {
+        folders.push(folder.clone());
+        folders.sort();
+        folders.dedup();
+    }
+    (folders, files)
+}
+
+/// Builds manifest of every file under referenced folders and of referenced files,
+/// returns it with the list of referenced paths which are missing
+pub fn generate(
+    playlist: &Playlist,
+    node_config: &NodeConfig,
+) -> io::Result<(Manifest, Vec<String>)> {
+    let media_folder = Path::new(&node_config.media.folder);
+    let (folders, files) = get_library_references(playlist, node_config);
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+    for folder in folders {
+        let path = media_folder.join(&folder);
+        if path.is_dir() {
+            collect_files(&path, &mut paths)?;
+        } else {
+            missing.push(folder);
+        }
+    }
+    for file in files {
+        let path = media_folder.join(&file);
+        if path.is_file() {
+            paths.push(path);
+        } else {
+            missing.push(file);
+        }
+    }
+    paths.sort();
+    paths.dedup();
+
+    let mut manifest = Manifest::default();
+    for path in paths {
+        manifest.files.push(ManifestEntry {
+            path: get_relative_path(media_folder, &path),
+            size: fs::metadata(&path)?.len(),
+            sha256: hash_file(&path)?,
+        });
+    }
+    Ok((manifest, missing))
+}
+
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns path relative to media folder with unix style slashes
+fn get_relative_path(media_folder: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(media_folder).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Media file which does not match integrity manifest
+#[derive(Debug, Clone)]
+pub struct IntegrityProblem {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Checks media files against integrity manifest, files are hashed again only when their size or mtime changes
+pub struct IntegrityChecker {
+    manifest_path: PathBuf,
+    manifest_mtime: Option<SystemTime>,
+    manifest: Manifest,
+    verified: HashMap<String, Option<(u64, SystemTime)>>,
+}
+
+impl IntegrityChecker {
+    pub fn new(node_config: &NodeConfig) -> IntegrityChecker {
+        IntegrityChecker {
+            manifest_path: get_manifest_path(node_config),
+            manifest_mtime: None,
+            manifest: Manifest::default(),
+            verified: HashMap::new(),
+        }
+    }
+
+    /// Returns problems found since previous check, nothing is checked if there is no manifest
+    pub fn check(&mut self, media_folder: &Path) -> Vec<IntegrityProblem> {
+        self.reload_manifest();
+
+        let mut problems: Vec<IntegrityProblem> = Vec::new();
+        for entry in self.manifest.files.iter() {
+            let path = media_folder.join(&entry.path);
+            let metadata = fs::metadata(&path)
+                .ok()
+                .and_then(|m| Some((m.len(), m.modified().ok()?)));
+            if self.verified.get(&entry.path) == Some(&metadata) {
+                continue;
+            }
+            self.verified.insert(entry.path.clone(), metadata);
+
+            let reason = match metadata {
+                None => "missing".to_string(),
+                Some((size, _)) if size != entry.size => {
+                    format!("size {} does not match manifest size {}", size, entry.size)
+                }
+                Some(_) => match hash_file(&path) {
+                    Ok(hash) if hash == entry.sha256 => continue,
+                    Ok(_) => "SHA-256 does not match manifest".to_string(),
+                    Err(e) => e.to_string(),
+                },
+            };
+            problems.push(IntegrityProblem { path, reason });
+        }
+        problems
+    }
+
+    fn reload_manifest(&mut self) {
+        let mtime = fs::metadata(&self.manifest_path)
+            .and_then(|m| m.modified())
+            .ok();
+        if mtime == self.manifest_mtime {
+            return;
+        }
+        self.manifest_mtime = mtime;
+        self.verified.clear();
+        self.manifest = match mtime {
+            None => Manifest::default(),
+            Some(_) => match Manifest::read_from_file(&self.manifest_path) {
+                Ok(m) => {
+                    log::info!(
+                        "integrity manifest {:?} loaded, {} files",
+                        self.manifest_path,
+                        m.files.len()
+                    );
+                    m
+                }
+                Err(e) => {
+                    log::error!(
+                        "cannot read integrity manifest {:?}: {}",
+                        self.manifest_path,
+                        e
+                    );
+                    Manifest::default()
+                }
+            },
+        };
+    }
+}
+
+/// Returns lowercase hex SHA-256 of file content
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// Table key listing inherited keys to remove: `_remove = ["key", 2024-12-31]`
+pub const REMOVE_KEY: &str = "_remove";
+
+/// Table key making table replace inherited one instead of merging: `_replace = true`
+pub const REPLACE_KEY: &str = "_replace";
+
+/// Merges layer into base: tables are merged key by key, other values are replaced
+pub fn merge_values(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Table(base), Value::Table(mut layer)) if !is_replaced(&layer) => {
+            for key in get_removed_keys(&layer) {
+                base.remove(&key);
+            }
+            layer.remove(REMOVE_KEY);
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(inherited) => merge_values(inherited, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Removes merge directives from value and its nested tables
+pub fn strip_directives(value: &mut Value) {
+    match value {
+        Value::Table(table) => {
+            table.remove(REMOVE_KEY);
+            table.remove(REPLACE_KEY);
+            table.iter_mut().for_each(|(_, v)| strip_directives(v));
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_directives),
+        _ => {}
+    }
+}
+
+fn is_replaced(table: &toml::value::Table) -> bool {
+    table.get(REPLACE_KEY).and_then(Value::as_bool) == Some(true)
+}
+
+/// Returns keys listed for removal, dates and other values are converted to their TOML notation
+fn get_removed_keys(table: &toml::value::Table) -> Vec<String> {
+    let Some(Value::Array(keys)) = table.get(REMOVE_KEY) else {
+        return Vec::new();
+    };
+    keys.iter()
+        .map(|key| match key {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+        .collect()
+}
+
+/// Effective leaf settings of merged layers with documents which define them,
+/// keys are dotted paths, arrays are leaves
+#[derive(Debug, Default)]
+pub struct SettingOrigins {
+    settings: BTreeMap<String, (Value, PathBuf)>,
+}
+
+impl SettingOrigins {
+    /// Applies layer the same way as `merge_values` does
+    pub fn apply(&mut self, layer: &Value, origin: &Path) {
+        match layer {
+            Value::Table(table) => self.apply_table("", table, origin),
+            _ => self.apply_value(String::new(), layer, origin),
+        }
+    }
+
+    /// Returns settings sorted by key: key, value and origin
+    pub fn into_settings(self) -> Vec<(String, Value, PathBuf)> {
+        self.settings
+            .into_iter()
+            .map(|(key, (value, origin))| (key, value, origin))
+            .collect()
+    }
+
+    fn apply_table(&mut self, path: &str, table: &toml::value::Table, origin: &Path) {
+        for key in get_removed_keys(table) {
+            self.remove(&join_path(path, &key));
+        }
+        for (key, value) in table.iter() {
+            if key != REMOVE_KEY && key != REPLACE_KEY {
+                self.apply_value(join_path(path, key), value, origin);
+            }
+        }
+    }
+
+    fn apply_value(&mut self, path: String, value: &Value, origin: &Path) {
+        match value {
+            Value::Table(table) => {
+                if is_replaced(table) {
+                    self.remove(&path);
+                } else {
+                    // inherited scalar or array is replaced by table
+                    self.settings.remove(&path);
+                }
+                self.apply_table(&path, table, origin);
+            }
+            _ => {
+                self.remove(&path);
+                self.settings
+                    .insert(path, (value.clone(), origin.to_path_buf()));
+            }
+        }
+    }
+
+    /// Removes setting with given path and its nested settings
+    fn remove(&mut self, path: &str) {
+        let prefix = format!("{}.", path);
+        self.settings
+            .retain(|key, _| key != path && !key.starts_with(&prefix));
+    }
+}
+
+/// Returns dotted path, keys which are not bare TOML keys are quoted
+pub fn join_path(path: &str, key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    let key = match bare {
+        true => key.to_string(),
+        false => format!("{:?}", key),
+    };
+    match path.is_empty() {
+        true => key,
+        false => format!("{}.{}", path, key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(documents: &[&str]) -> Value {
+        let mut layers = documents
+            .iter()
+            .map(|d| toml::from_str::<Value>(d).unwrap());
+        let mut value = layers.next().unwrap();
+        for layer in layers {
+            merge_values(&mut value, layer);
+        }
+        strip_directives(&mut value);
+        value
+    }
+
+    #[test]
+    fn tables_are_merged_key_by_key() {
+        let value = merged(&["a = 1\n[t]\nx = 1\ny = [1, 2]", "[t]\ny = [3]\nz = 'z'"]);
+        assert_eq!(
+            value,
+            toml::from_str("a = 1\n[t]\nx = 1\ny = [3]\nz = 'z'").unwrap()
+        );
+    }
+
+    #[test]
+    fn inherited_keys_are_removed() {
+        let value = merged(&[
+            "[t]\nx = 1\n'2024-12-31' = 2\nz = 3",
+            "[t]\n_remove = ['x', 2024-12-31]",
+        ]);
+        assert_eq!(value, toml::from_str("[t]\nz = 3").unwrap());
+    }
+
+    #[test]
+    fn replaced_table_is_not_merged() {
+        let value = merged(&["[t]\nx = 1\n[t.n]\ny = 2", "[t]\n_replace = true\nz = 3"]);
+        assert_eq!(value, toml::from_str("[t]\nz = 3").unwrap());
+    }
+
+    #[test]
+    fn origins_follow_merge_rules() {
+        let mut origins = SettingOrigins::default();
+        let layers = [
+            ("first", "[t]\nx = 1\ny = 2\n[t.n]\na = 1\n[u]\nb = 1"),
+            (
+                "second",
+                "[t]\ny = 3\n_remove = ['x']\n[u]\n_replace = true\nc = 1",
+            ),
+        ];
+        for (origin, document) in layers {
+            origins.apply(&toml::from_str(document).unwrap(), Path::new(origin));
+        }
+        let settings: Vec<(String, String)> = origins
+            .into_settings()
+            .into_iter()
+            .map(|(key, _, origin)| (key, origin.to_string_lossy().to_string()))
+            .collect();
+        let expected = [("t.n.a", "first"), ("t.y", "second"), ("u.c", "second")];
+        let expected: Vec<(String, String)> = expected
+            .iter()
+            .map(|(k, o)| (k.to_string(), o.to_string()))
+            .collect();
+        assert_eq!(settings, expected);
+    }
+}
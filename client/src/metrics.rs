@@ -0,0 +1,197 @@
+use crate::config::NodeConfig;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// Node counters and gauges exposed in Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub state: String,
+    pub tracks_played: u64,
+    pub ad_blocks_played: u64,
+    pub ad_blocks_missed: u64,
+    pub playback_failures: u64,
+    pub last_playback: Option<Instant>,
+    pub playlist_reloads: u64,
+    pub playlist_errors: u64,
+    pub library_files: usize,
+    pub ad_slot_lag_last: f64,
+    pub ad_slot_lag_sum: f64,
+    pub ad_slot_lag_count: u64,
+}
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+impl Metrics {
+    /// Records delay between advertizement slot time and block start, in seconds
+    pub fn observe_ad_slot_lag(&mut self, lag: f64) {
+        self.ad_slot_lag_last = lag;
+        self.ad_slot_lag_sum += lag;
+        self.ad_slot_lag_count += 1;
+    }
+
+    /// Returns metrics in Prometheus text exposition format
+    pub fn render(&self, node_name: &str) -> String {
+        let mut out = String::new();
+        let label = format!("node=\"{}\"", node_name);
+        let mut metric = |name: &str, kind: &str, help: &str, labels: &str, value: String| {
+            let _ = writeln!(out, "# HELP distributed_player_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE distributed_player_{} {}", name, kind);
+            let _ = writeln!(out, "distributed_player_{}{{{}}} {}", name, labels, value);
+        };
+
+        metric(
+            "state",
+            "gauge",
+            "Current player state.",
+            &format!("{},state=\"{}\"", label, self.state),
+            "1".to_string(),
+        );
+        metric(
+            "tracks_played_total",
+            "counter",
+            "Music tracks started.",
+            &label,
+            self.tracks_played.to_string(),
+        );
+        metric(
+            "ad_blocks_played_total",
+            "counter",
+            "Advertizement blocks played till the end.",
+            &label,
+            self.ad_blocks_played.to_string(),
+        );
+        metric(
+            "ad_blocks_missed_total",
+            "counter",
+            "Advertizement slots not started or interrupted blocks not resumed.",
+            &label,
+            self.ad_blocks_missed.to_string(),
+        );
+        metric(
+            "playback_failures_total",
+            "counter",
+            "Media files which failed to play.",
+            &label,
+            self.playback_failures.to_string(),
+        );
+        let since_playback = self
+            .last_playback
+            .map_or(f64::NAN, |t| t.elapsed().as_secs_f64());
+        metric(
+            "seconds_since_last_playback",
+            "gauge",
+            "Seconds since media was playing the last time.",
+            &label,
+            since_playback.to_string(),
+        );
+        metric(
+            "playlist_reloads_total",
+            "counter",
+            "Successful playlist reads.",
+            &label,
+            self.playlist_reloads.to_string(),
+        );
+        metric(
+            "playlist_errors_total",
+            "counter",
+            "Failed playlist reads.",
+            &label,
+            self.playlist_errors.to_string(),
+        );
+        metric(
+            "library_files",
+            "gauge",
+            "Playable music files for current date.",
+            &label,
+            self.library_files.to_string(),
+        );
+        metric(
+            "last_ad_slot_lag_seconds",
+            "gauge",
+            "Delay of the last advertizement block start after its slot time.",
+            &label,
+            self.ad_slot_lag_last.to_string(),
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP distributed_player_ad_slot_lag_seconds Delay of advertizement blocks starts after slot time."
+        );
+        let _ = writeln!(out, "# TYPE distributed_player_ad_slot_lag_seconds summary");
+        let _ = writeln!(
+            out,
+            "distributed_player_ad_slot_lag_seconds_sum{{{}}} {}",
+            label, self.ad_slot_lag_sum
+        );
+        let _ = writeln!(
+            out,
+            "distributed_player_ad_slot_lag_seconds_count{{{}}} {}",
+            label, self.ad_slot_lag_count
+        );
+        out
+    }
+}
+
+/// Starts HTTP listener with `/metrics` endpoint (if configured)
+pub fn start_server(node_config: &NodeConfig, metrics: SharedMetrics) {
+    let Some(config) = node_config.metrics.as_ref() else {
+        return;
+    };
+    let address = config.address.clone();
+    let listener = match TcpListener::bind(&address) {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("cannot bind metrics endpoint to {}: {}", address, e);
+            return;
+        }
+    };
+    log::info!("metrics endpoint listens on {}", address);
+
+    let node_name = node_config.node.name.clone().unwrap_or_default();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let node_name = node_name.clone();
+            let metrics = metrics.clone();
+            thread::spawn(move || handle_connection(stream, &node_name, &metrics));
+        }
+    });
+}
+
+/// Answers single HTTP request, only `GET /metrics` is supported
+fn handle_connection(stream: TcpStream, node_name: &str, metrics: &SharedMetrics) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(reader);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // skip headers
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => match metrics.lock() {
+            Ok(m) => ("200 OK", m.render(node_name)),
+            Err(_) => ("500 Internal Server Error", String::new()),
+        },
+        _ => ("404 Not Found", String::new()),
+    };
+
+    let mut writer = stream;
+    let _ = write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+}
@@ -0,0 +1,227 @@
+use crate::config::NodeConfig;
+use crate::discovery::{self, PeerEvent};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+/// Synchronized files which node can share with peers, by SHA-256: path and size
+pub type SharedLibrary = Arc<RwLock<HashMap<String, (PathBuf, u64)>>>;
+
+/// Content servers of peers from the same site
+#[derive(Clone, Default)]
+pub struct PeerDirectory {
+    peers: Arc<Mutex<HashMap<String, SocketAddr>>>,
+}
+
+impl PeerDirectory {
+    /// Returns content server addresses of peers advertised on LAN
+    pub fn get_peers(&self) -> Vec<SocketAddr> {
+        match self.peers.lock() {
+            Ok(peers) => peers.values().copied().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn update(&self, event: PeerEvent) {
+        let Ok(mut peers) = self.peers.lock() else {
+            return;
+        };
+        match event {
+            PeerEvent::Found { node, address } => {
+                if peers.insert(node.clone(), address) != Some(address) {
+                    log::info!("peer {} found at {}", node, address);
+                }
+            }
+            PeerEvent::Lost { node } => {
+                if peers.remove(&node).is_some() {
+                    log::info!("peer {} is gone", node);
+                }
+            }
+        }
+    }
+}
+
+/// Starts peer content server and mDNS discovery of peers from the same site (if configured)
+pub fn start(node_config: &NodeConfig, library: SharedLibrary) -> Option<PeerDirectory> {
+    let config = node_config.sync.as_ref()?.peers.as_ref()?;
+    let node_name = node_config.node.name.clone().unwrap_or_default();
+
+    let listener = match TcpListener::bind(&config.address) {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!(
+                "cannot bind peer content server to {}: {}",
+                config.address,
+                e
+            );
+            return None;
+        }
+    };
+    let port = listener.local_addr().ok()?.port();
+    log::info!("peer content server listens on {}", config.address);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let library = library.clone();
+            thread::spawn(move || handle_connection(stream, &library));
+        }
+    });
+
+    let directory = PeerDirectory::default();
+    let peers = directory.clone();
+    let discovery = discovery::discover_peers(
+        &node_name,
+        config.site.as_deref().unwrap_or_default(),
+        port,
+        config.interface.as_deref(),
+        move |event| peers.update(event),
+    );
+    // mDNS daemon keeps running after its handle is dropped
+    if let Err(e) = discovery {
+        e.log();
+    }
+    Some(directory)
+}
+
+/// Answers single HTTP request, only `GET /sha256/{hash}` with optional byte range is supported
+fn handle_connection(stream: TcpStream, library: &SharedLibrary) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(reader);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut range: Option<(u64, Option<u64>)> = None;
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = parse_range(value.trim());
+            }
+        }
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let file = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => path
+            .strip_prefix("/sha256/")
+            .and_then(|hash| library.read().ok()?.get(hash).cloned())
+            .and_then(|(path, size)| {
+                let file = File::open(path).ok()?;
+                // file could be changed locally since synchronization
+                (file.metadata().ok()?.len() == size).then_some((file, size))
+            }),
+        _ => None,
+    };
+
+    let mut writer = stream;
+    let Some((mut file, size)) = file else {
+        let _ = write!(
+            writer,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        return;
+    };
+    let (status, start, end) = match range {
+        Some((start, end)) if start < size && end.is_none_or(|end| end >= start) => {
+            let end = end.unwrap_or(size - 1).min(size - 1);
+            (
+                format!(
+                    "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                    start, end, size
+                ),
+                start,
+                end,
+            )
+        }
+        Some(_) => {
+            let _ = write!(
+                writer,
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                size
+            );
+            return;
+        }
+        None => ("200 OK".to_string(), 0, size.saturating_sub(1)),
+    };
+    let len = if size == 0 { 0 } else { end - start + 1 };
+    if file.seek(SeekFrom::Start(start)).is_err() {
+        return;
+    }
+    let _ = write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, len
+    );
+    let _ = io::copy(&mut file.take(len), &mut writer);
+}
+
+/// Parses `bytes=start-[end]` range
+fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    Some((start.trim().parse().ok()?, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+    use tempfile::TempDir;
+
+    /// Serves single file of 1000 bytes and returns response to request with given range
+    fn request_range(range: &str) -> String {
+        let folder = TempDir::new().unwrap();
+        let path = folder.path().join("track.mp3");
+        std::fs::write(&path, [7u8; 1000]).unwrap();
+        let library = SharedLibrary::default();
+        library
+            .write()
+            .unwrap()
+            .insert("hash".to_string(), (path, 1000));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        write!(
+            client,
+            "GET /sha256/hash HTTP/1.1\r\nRange: {}\r\n\r\n",
+            range
+        )
+        .unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &library);
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn ranges_are_served_partially() {
+        let response = request_range("bytes=500-599");
+        assert!(response.starts_with("HTTP/1.1 206"), "{}", response);
+        assert!(response.contains("Content-Range: bytes 500-599/1000"));
+        assert!(response.contains("Content-Length: 100\r\n"));
+
+        let response = request_range("bytes=900-");
+        assert!(response.contains("Content-Range: bytes 900-999/1000"));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_refused() {
+        for range in ["bytes=500-100", "bytes=1000-"] {
+            let response = request_range(range);
+            assert!(response.starts_with("HTTP/1.1 416"), "{}", response);
+            assert!(response.contains("Content-Range: bytes */1000"));
+        }
+    }
+}
@@ -1,16 +1,81 @@
 use crate::{
     config::NodeConfig,
-    playlist::{self, Playlist},
+    control::{self, ControlCommand, NodeStatus, SharedStatus},
+    error::{Error, Result},
+    events::EventsJournal,
+    heartbeat,
+    manifest::IntegrityChecker,
+    metrics::{self, Metrics, SharedMetrics},
+    playlist::{self, ContentType, EventOccurrence, FadeCurve, Playlist},
+    quarantine::Quarantine,
+    rollout::{self, PlaylistLoader, RolloutStatus},
+    signal::{self, Output, Signal},
+    silence::{self, SilentInterval},
+    state::{self, PendingEvent, SavedState, StateStore},
+    sync, tts,
 };
 use chrono::{prelude::*, Duration};
 use glob::glob;
 use log;
 use rand::prelude::*;
 use std::{
+    collections::VecDeque,
+    fs, mem,
     path::{Path, PathBuf},
-    thread,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration as StdDuration, Instant},
 };
-use vlc::{Media, MediaPlayerAudioEx};
+use vlc::{MediaPlayerAudioEx, State};
+
+/// Content layers, each layer preempts the lower ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    BackgroundMusic,
+    ScheduledContent,
+    OperatorMessage,
+    Emergency,
+}
+
+/// Content priority: layer and priority level inside the layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Priority(pub Layer, pub u8);
+
+/// Priority of advertizement blocks and announcements
+const SCHEDULED_CONTENT_PRIORITY: Priority =
+    Priority(Layer::ScheduledContent, playlist::DEFAULT_EVENT_PRIORITY);
+
+/// Playback start timeout
+const PLAYBACK_START_TIMEOUT: StdDuration = StdDuration::from_secs(3);
+
+/// Priority of background music
+const MUSIC_PRIORITY: Priority = Priority(Layer::BackgroundMusic, 0);
+
+/// Player state is saved with this interval while music is playing
+const STATE_SAVE_INTERVAL: StdDuration = StdDuration::from_secs(10);
+
+/// Playlist is re-read at least with this interval while player is idle
+const IDLE_RECHECK_INTERVAL: StdDuration = StdDuration::from_secs(60);
+
+/// Missing playlist and media files are looked for with this interval
+const RETRY_INTERVAL: StdDuration = StdDuration::from_secs(1);
+
+/// Schedule timers fire with this delay, so schedule instant is surely passed
+const SCHEDULE_TIMER_MARGIN: StdDuration = StdDuration::from_millis(10);
+
+/// Count of the latest errors reported in node status
+const RECENT_ERRORS_COUNT: usize = 20;
+
+/// Silence starting closer than this is skipped at once, backend reports playback time roughly
+const SILENCE_SKIP_TOLERANCE_MS: i64 = 100;
+
+#[derive(Debug)]
+pub struct Message {
+    pub layer: Layer,
+    pub file: PathBuf,
+}
 
 #[derive(Debug)]
 pub enum PlayerState {
@@ -18,73 +83,250 @@ pub enum PlayerState {
     MusicPlaying(Vec<PathBuf>),
     Advertizement(Vec<PathBuf>),
     TimeAnnouncement(PathBuf),
+    WorkingHoursAnnouncement(PathBuf),
+    Event(EventOccurrence),
+    Message(Message),
+}
+
+impl PlayerState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerState::Stopped => "Stopped",
+            PlayerState::MusicPlaying(_) => "MusicPlaying",
+            PlayerState::Advertizement(_) => "Advertizement",
+            PlayerState::TimeAnnouncement(_) => "TimeAnnouncement",
+            PlayerState::WorkingHoursAnnouncement(_) => "WorkingHoursAnnouncement",
+            PlayerState::Event(_) => "Event",
+            PlayerState::Message(_) => "Message",
+        }
+    }
 }
 
 pub struct Player<'a> {
     vlc_instance: vlc::Instance,
     media_player: vlc::MediaPlayer,
+    voice_player: vlc::MediaPlayer,
     node_config: &'a NodeConfig,
     status: PlayerState,
     playlist: Option<Playlist>,
     next_track_index: usize,
+    music_files: Vec<PathBuf>,
     random_generator: ThreadRng,
+    last_announcement_check: NaiveDateTime,
+    events_journal: EventsJournal,
+    signals: Receiver<Signal>,
+    signal_sender: Sender<Signal>,
+    deferred_signals: VecDeque<Signal>,
+    pending_messages: Vec<Message>,
+    cancel_requested: bool,
+    interrupted_advertizement: Option<Vec<PathBuf>>,
+    current_track: Option<PathBuf>,
+    interrupted_track: Option<(PathBuf, i64)>,
+    shared_status: SharedStatus,
+    metrics: SharedMetrics,
+    last_ad_slots_check: NaiveDateTime,
+    quarantine: Quarantine,
+    integrity: IntegrityChecker,
+    playlist_loader: PlaylistLoader,
+    last_playback: Instant,
+    last_playback_time: Option<i64>,
+    last_playback_time_change: Instant,
+    dead_air_alert: bool,
+    track_silence: Option<Vec<SilentInterval>>,
+    state_store: StateStore,
+    last_state_save: Instant,
+    restored_music_order: Option<(Vec<PathBuf>, usize)>,
+    history: Vec<PathBuf>,
+    last_advertizement_slot: Option<NaiveDateTime>,
+    last_time_announcement: Option<NaiveDateTime>,
+    pending_event: Option<PendingEvent>,
+    restored_event: Option<PendingEvent>,
 }
 
 impl Player<'_> {
-    pub fn new<'a>(node_config: &'a NodeConfig) -> Player {
-        let vlc_instance = vlc::Instance::new().expect("Cannot instaniate libvlc!");
-        let media_player =
-            vlc::MediaPlayer::new(&vlc_instance).expect("Cannot instaniate vlc media player");
-        Player {
+    pub fn new(node_config: &NodeConfig) -> Result<Player<'_>> {
+        let vlc_instance = vlc::Instance::new()
+            .ok_or_else(|| Error::Backend("cannot instantiate libvlc".to_string()))?;
+        let media_player = vlc::MediaPlayer::new(&vlc_instance)
+            .ok_or_else(|| Error::Backend("cannot instantiate vlc media player".to_string()))?;
+        let voice_player = vlc::MediaPlayer::new(&vlc_instance)
+            .ok_or_else(|| Error::Backend("cannot instantiate vlc media player".to_string()))?;
+        let quarantine = Quarantine::load(node_config);
+        let shared_status = Arc::new(Mutex::new(NodeStatus {
+            quarantine: quarantine.report(),
+            ..Default::default()
+        }));
+        let (signal_sender, signals) = mpsc::channel();
+        signal::attach_media_events(&media_player, Output::Main, &signal_sender)?;
+        signal::attach_media_events(&voice_player, Output::Voice, &signal_sender)?;
+        control::start_server(node_config, shared_status.clone(), signal_sender.clone());
+        heartbeat::start(node_config, shared_status.clone());
+        sync::start(node_config);
+        let metrics = Arc::new(Mutex::new(Metrics::default()));
+        metrics::start_server(node_config, metrics.clone());
+        let state_store = StateStore::open(node_config);
+        // working hours can last past midnight, so state is kept for working day, not calendar one
+        let now = Local::now().naive_local();
+        let working_day_start = Playlist::read_from_config(node_config)
+            .ok()
+            .and_then(|playlist| playlist.get_working_day_start(now))
+            .unwrap_or(now.date().and_time(NaiveTime::MIN));
+        let saved_state = state_store.restore(working_day_start).unwrap_or_default();
+        Ok(Player {
             vlc_instance: vlc_instance,
             media_player,
+            voice_player,
             status: PlayerState::Stopped,
             node_config: node_config,
             next_track_index: 0,
+            music_files: Vec::new(),
             playlist: None,
             random_generator: thread_rng(),
-        }
+            last_announcement_check: Local::now().naive_local(),
+            events_journal: EventsJournal::load(node_config),
+            signals,
+            signal_sender,
+            deferred_signals: VecDeque::new(),
+            pending_messages: Vec::new(),
+            cancel_requested: false,
+            interrupted_advertizement: saved_state.interrupted_advertizement,
+            current_track: None,
+            interrupted_track: saved_state.current_track.zip(saved_state.position),
+            shared_status,
+            metrics,
+            last_ad_slots_check: Local::now().naive_local(),
+            quarantine,
+            integrity: IntegrityChecker::new(node_config),
+            playlist_loader: PlaylistLoader::new(node_config),
+            last_playback: Instant::now(),
+            last_playback_time: None,
+            last_playback_time_change: Instant::now(),
+            dead_air_alert: false,
+            track_silence: None,
+            state_store,
+            last_state_save: Instant::now(),
+            restored_music_order: Some((saved_state.music_files, saved_state.next_track_index))
+                .filter(|(files, _)| !files.is_empty()),
+            history: saved_state.history,
+            last_advertizement_slot: saved_state.last_advertizement_slot,
+            last_time_announcement: saved_state.last_time_announcement,
+            pending_event: None,
+            restored_event: saved_state.pending_event,
+        })
     }
 
     pub fn start(&mut self) {
         loop {
             self.dispatch();
+            self.save_state();
 
-            if let PlayerState::MusicPlaying(music_files) = &self.status {
-                let next_track_path = &music_files[self.next_track_index];
-                log::info!("start {:?}", next_track_path);
-                self.play_media_non_blocking(next_track_path.as_path());
+            let state = self.status.name();
+            self.update_shared_status(|status| status.state = state.to_string());
+            self.update_metrics(|m| m.state = state.to_string());
+            self.count_missed_ad_slots();
 
-                self.next_track_index += 1;
-                if self.next_track_index == music_files.len() {
-                    self.next_track_index = 0;
-                }
-            } else if let PlayerState::Advertizement(advertizement_files) = &self.status {
-                log::info!("start adv block");
+            match mem::replace(&mut self.status, PlayerState::Stopped) {
+                PlayerState::MusicPlaying(music_files) => {
+                    let interrupted_track = self
+                        .interrupted_track
+                        .take()
+                        .filter(|(path, _)| music_files.contains(path));
 
-                let mut start_jingle_file_path: Option<String> = None;
-                let mut end_jingle_file_path: Option<String> = None;
+                    if let Some((track_path, time)) = interrupted_track {
+                        log::info!(
+                            state = "MusicPlaying", file:? = track_path, position = time;
+                            "resume {:?} from {} ms", track_path, time
+                        );
+                        self.resume_media(&track_path, time);
+                        self.start_silence_analysis(&track_path);
+                        state::push_history(&mut self.history, &track_path);
+                        self.current_track = Some(track_path);
+                    } else {
+                        let next_track_path = music_files[self.next_track_index].clone();
+                        self.next_track_index += 1;
+                        if self.next_track_index == music_files.len() {
+                            self.next_track_index = 0;
+                        }
 
-                if let Some(pl) = self.playlist.as_ref() {
-                    (start_jingle_file_path, end_jingle_file_path) =
-                        pl.get_advertizement_jingles_file_path();
+                        log::info!(
+                            state = "MusicPlaying", file:? = next_track_path;
+                            "start {:?}", next_track_path
+                        );
+                        if let Err(e) =
+                            self.play_media_non_blocking(&next_track_path, ContentType::Music)
+                        {
+                            // skip broken track
+                            self.handle_media_error(e);
+                            continue;
+                        }
+                        self.update_metrics(|m| m.tracks_played += 1);
+                        self.start_silence_analysis(&next_track_path);
+                        state::push_history(&mut self.history, &next_track_path);
+                        self.current_track = Some(next_track_path);
+                    }
+                    self.status = PlayerState::MusicPlaying(music_files);
                 }
-
-                if let Some(p) = start_jingle_file_path {
-                    self.play_media_blocking(Path::new(&p));
+                PlayerState::Advertizement(advertizement_block) => {
+                    self.play_advertizement_block(advertizement_block);
                 }
-                for advert in advertizement_files.iter() {
-                    log::info!("start adv {:?}", advert);
-                    self.play_media_blocking(&advert);
+                PlayerState::TimeAnnouncement(announcement_file_path) => {
+                    self.play_media_blocking(
+                        &announcement_file_path,
+                        SCHEDULED_CONTENT_PRIORITY,
+                        ContentType::Announcement,
+                    );
                 }
-                if let Some(p) = end_jingle_file_path {
-                    self.play_media_blocking(Path::new(&p));
+                PlayerState::WorkingHoursAnnouncement(announcement_file_path) => {
+                    log::info!(
+                        state = "WorkingHoursAnnouncement", file:? = announcement_file_path;
+                        "start working hours announcement {:?}",
+                        announcement_file_path
+                    );
+                    self.play_media_blocking(
+                        &announcement_file_path,
+                        SCHEDULED_CONTENT_PRIORITY,
+                        ContentType::Announcement,
+                    );
                 }
-                log::info!("end adv block");
-                self.status = PlayerState::Stopped;
-            } else if let PlayerState::TimeAnnouncement(announcement_file_path) = &self.status {
-                self.play_media_blocking(&announcement_file_path);
-                self.status = PlayerState::Stopped;
+                PlayerState::Event(occurrence) => {
+                    log::info!(
+                        state = "Event", event = occurrence.id.as_str(), slot:% = occurrence.datetime;
+                        "start event {} scheduled at {}",
+                        occurrence.id,
+                        occurrence.datetime
+                    );
+                    self.events_journal.mark_played(&occurrence);
+                    self.pending_event = Some(PendingEvent {
+                        id: occurrence.id.clone(),
+                        datetime: occurrence.datetime,
+                    });
+                    self.save_state();
+
+                    let priority = Priority(Layer::ScheduledContent, occurrence.priority());
+                    for file in self.load_event_media_files(&occurrence).iter() {
+                        if !self.play_media_blocking(file, priority, ContentType::Event) {
+                            log::warn!("event {} interrupted", occurrence.id);
+                            break;
+                        }
+                    }
+                    self.pending_event = None;
+                    self.save_state();
+                }
+                PlayerState::Message(message) => {
+                    log::info!(
+                        state = "Message", layer:? = message.layer, file:? = message.file;
+                        "start {:?} message {:?}", message.layer, message.file
+                    );
+                    self.cancel_requested = false;
+                    if !self.play_media_blocking(
+                        &message.file,
+                        Priority(message.layer, 0),
+                        ContentType::Message,
+                    ) {
+                        log::warn!("{:?} message interrupted", message.layer);
+                    }
+                }
+                PlayerState::Stopped => {}
             }
         }
     }
@@ -93,48 +335,179 @@ impl Player<'_> {
     fn dispatch(&mut self) {
         match &self.status {
             PlayerState::Stopped => {
-                let pl = self.read_playlist();
+                // it's operator or emergency message, played even without playlist or media files
+                self.receive_control_commands();
+                if let Some(message) = self.take_pending_message() {
+                    self.status = PlayerState::Message(message);
+                    return;
+                }
+
+                // message was received while waiting for playlist
+                let Some(pl) = self.read_playlist() else {
+                    return;
+                };
+                self.quarantine.release_changed();
+                self.check_integrity();
 
                 let current_datetime = Local::now().naive_local();
 
+                // it's event interrupted by node restart
+                if let Some(occurrence) = self.take_restored_event(&pl) {
+                    log::info!("resume event {} after restart", occurrence.id);
+                    self.status = PlayerState::Event(occurrence);
+                    self.playlist = Some(pl);
+                    return;
+                }
+
+                // it's scheduled event
+                if let Some(occurrence) = self.get_due_event(&pl, current_datetime, MUSIC_PRIORITY)
+                {
+                    self.status = PlayerState::Event(occurrence);
+                    self.playlist = Some(pl);
+                    return;
+                }
+
+                // it's interrupted advertizement block
+                if let Some(advertizement_block) = self.interrupted_advertizement.take() {
+                    log::info!("resume adv block");
+                    self.status = PlayerState::Advertizement(advertizement_block);
+                    self.playlist = Some(pl);
+                    return;
+                }
+
+                // it's opening or closing announcement
+                let announcement_file = pl.get_working_hours_announcement_file_path(
+                    self.last_announcement_check,
+                    current_datetime,
+                );
+                self.last_announcement_check = current_datetime;
+                if let Some(announcement_file) = announcement_file {
+                    self.status = PlayerState::WorkingHoursAnnouncement(announcement_file);
+                    self.playlist = Some(pl);
+                    return;
+                }
+
                 if !pl.is_working_time(current_datetime) {
-                    wait_seconds(1);
+                    self.interrupted_track = None;
+                    self.playlist = Some(pl);
+                    self.wait_idle();
                     return;
                 }
 
                 let music_folders = pl.get_music_folders_for_date(current_datetime.date());
-                if music_folders.len() == 0 {
-                    wait_seconds(1);
+                if music_folders.is_empty() {
+                    self.playlist = Some(pl);
+                    self.watch_dead_air();
+                    self.wait_idle();
                     return;
                 }
 
                 let music_files = self.load_media_files_list_from_dirs(&music_folders);
-                let total_music_files = music_files.len();
+                // message was received while waiting for media files
+                if music_files.is_empty() {
+                    self.playlist = Some(pl);
+                    return;
+                }
+                let library_files = music_files.len();
+                self.update_metrics(|m| m.library_files = library_files);
 
-                self.status = PlayerState::MusicPlaying(music_files);
+                // restored order is used while music files are the same
+                if let Some((restored_files, next_track_index)) = self.restored_music_order.take() {
+                    let same_files = restored_files.len() == music_files.len()
+                        && restored_files.iter().all(|f| music_files.contains(f));
+                    if same_files && next_track_index < restored_files.len() {
+                        log::info!("continue music from track {}", next_track_index);
+                        self.next_track_index = next_track_index;
+                        self.music_files = restored_files.clone();
+                        self.status = PlayerState::MusicPlaying(restored_files);
+                        self.playlist = Some(pl);
+                        return;
+                    }
+                }
 
+                // music list shrinks when files are quarantined or removed by sync
+                self.next_track_index = get_track_index(self.next_track_index, music_files.len());
                 if let Some(music) = pl.music.as_ref() {
                     if let Some(shuffle) = music.shuffle.as_ref() {
                         if *shuffle {
-                            self.next_track_index =
-                                self.random_generator.gen_range(0..total_music_files);
+                            self.next_track_index = self.get_random_track_index(&music_files);
                         }
                     }
                 }
+                self.music_files = music_files.clone();
+                self.status = PlayerState::MusicPlaying(music_files);
                 self.playlist = Some(pl);
             }
 
             PlayerState::MusicPlaying(_) => {
                 let mut prev_dt: NaiveDateTime = Local::now().naive_local() - Duration::seconds(1);
                 loop {
-                    wait_seconds(1);
+                    let deadline = self.get_music_deadline();
+                    let signal = self.wait_signal(Output::Main, deadline);
+                    if self.last_state_save.elapsed() >= STATE_SAVE_INTERVAL {
+                        self.save_state();
+                    }
 
                     // track is over
-                    if !self.media_player.is_playing() {
+                    if matches!(
+                        signal,
+                        Some(Signal::MediaEnded(_)) | Some(Signal::MediaError(_))
+                    ) || !self.media_player.is_playing()
+                    {
+                        let failed = matches!(signal, Some(Signal::MediaError(_)))
+                            || self.media_player.state() == State::Error;
+                        if failed {
+                            if let Some(track) = self.current_track.clone() {
+                                self.handle_media_error(Error::MediaPlay {
+                                    path: track,
+                                    reason: "playback error".to_string(),
+                                });
+                            }
+                        }
                         self.status = PlayerState::Stopped;
                         return;
                     };
 
+                    // playback is stuck
+                    if self.is_playback_stuck() {
+                        self.media_player.stop();
+                        if let Some(track) = self.current_track.clone() {
+                            self.handle_media_error(Error::MediaStalled {
+                                path: track,
+                                reason: "playback is stuck".to_string(),
+                            });
+                        }
+                        self.status = PlayerState::Stopped;
+                        return;
+                    }
+
+                    // long silence inside track
+                    self.skip_track_silence();
+
+                    // volume schedule boundary
+                    self.ramp_music_volume();
+
+                    // it's operator message played over music
+                    if let Some(message) = self.take_voice_over_message() {
+                        log::info!("start {:?} message {:?}", message.layer, message.file);
+                        self.cancel_requested = false;
+                        if !self.play_voice_over(
+                            &message.file,
+                            Priority(message.layer, 0),
+                            ContentType::Message,
+                        ) {
+                            log::warn!("{:?} message interrupted", message.layer);
+                        }
+                        continue;
+                    }
+
+                    // it's message or scheduled event
+                    if self.has_preempting_content(MUSIC_PRIORITY) {
+                        self.interrupt_music();
+                        self.status = PlayerState::Stopped;
+                        return;
+                    }
+
                     let dt = Local::now().naive_local();
                     if let Some(pl) = self.playlist.as_ref() {
                         // working time is over
@@ -144,22 +517,69 @@ impl Player<'_> {
                             return;
                         }
 
-                        // it's advertizement
+                        // it's opening or closing announcement
+                        let announcement_file = pl.get_working_hours_announcement_file_path(
+                            self.last_announcement_check,
+                            dt,
+                        );
+                        self.last_announcement_check = dt;
+                        if let Some(announcement_file) = announcement_file {
+                            self.interrupt_music();
+                            self.status = PlayerState::WorkingHoursAnnouncement(announcement_file);
+                            return;
+                        }
+
+                        // it's advertizement, each slot is played once
+                        let advertizement_slot = dt.with_second(0).unwrap_or(dt);
                         let advertizement_folders = pl.get_advertizement_folders_for_datetime(dt);
-                        if advertizement_folders.len() > 0 {
-                            let advertizement_files =
+                        if !advertizement_folders.is_empty()
+                            && self.last_advertizement_slot != Some(advertizement_slot)
+                        {
+                            self.last_advertizement_slot = Some(advertizement_slot);
+                            let (start_jingle, end_jingle) =
+                                pl.get_advertizement_jingles_file_path();
+                            let mut advertizement_block =
                                 self.load_media_files_list_from_dirs(&advertizement_folders);
-                            self.fade_out();
-                            self.status = PlayerState::Advertizement(advertizement_files);
+                            // message was received while waiting for advertizement files
+                            if advertizement_block.is_empty() {
+                                self.interrupt_music();
+                                self.status = PlayerState::Stopped;
+                                return;
+                            }
+                            if let Some(p) = start_jingle {
+                                advertizement_block.insert(0, PathBuf::from(p));
+                            }
+                            if let Some(p) = end_jingle {
+                                advertizement_block.push(PathBuf::from(p));
+                            }
+                            self.interrupt_music();
+                            let lag = Local::now().naive_local() - advertizement_slot;
+                            self.update_metrics(|m| {
+                                m.observe_ad_slot_lag(lag.num_milliseconds() as f64 / 1000.0)
+                            });
+                            self.status = PlayerState::Advertizement(advertizement_block);
                             return;
                         }
 
-                        // it's time announcement
-                        if prev_dt.hour() != dt.hour() {
+                        // it's time announcement, not repeated after restart
+                        let announcement_slot = dt.with_second(0).unwrap_or(dt);
+                        if prev_dt.hour() != dt.hour()
+                            && self.last_time_announcement != Some(announcement_slot)
+                        {
                             if let Some(announcement_file) =
                                 pl.get_announcement_file_path_for_time(dt)
                             {
-                                self.fade_out();
+                                self.last_time_announcement = Some(announcement_slot);
+                                if pl.is_time_announcement_ducked() {
+                                    prev_dt = dt;
+                                    self.play_voice_over(
+                                        &announcement_file,
+                                        SCHEDULED_CONTENT_PRIORITY,
+                                        ContentType::Announcement,
+                                    );
+                                    continue;
+                                }
+                                self.interrupt_music();
                                 self.status = PlayerState::TimeAnnouncement(announcement_file);
                                 return;
                             }
@@ -168,75 +588,932 @@ impl Player<'_> {
                     }
                 }
             }
-            _ => wait_seconds(1),
+            _ => self.wait_idle(),
+        }
+    }
+
+    /// Plays advertizement block (including jingles), interrupted block is kept for resuming if enabled
+    fn play_advertizement_block(&mut self, advertizement_block: Vec<PathBuf>) {
+        let slot = self.last_advertizement_slot.map(|s| s.to_string());
+        log::info!(state = "Advertizement", slot = slot.as_deref(); "start adv block");
+        for (i, advert) in advertizement_block.iter().enumerate() {
+            log::info!(
+                state = "Advertizement", slot = slot.as_deref(), file:? = advert;
+                "start adv {:?}", advert
+            );
+            if !self.play_media_blocking(
+                advert,
+                SCHEDULED_CONTENT_PRIORITY,
+                ContentType::Advertizement,
+            ) {
+                let resume = self
+                    .playlist
+                    .as_ref()
+                    .is_some_and(|pl| pl.is_interrupted_advertizement_resumed());
+                if resume {
+                    log::warn!("adv block interrupted, will be resumed from {:?}", advert);
+                    self.interrupted_advertizement = Some(advertizement_block[i..].to_vec());
+                } else {
+                    log::warn!("adv block interrupted");
+                    self.update_metrics(|m| m.ad_blocks_missed += 1);
+                }
+                return;
+            }
         }
+        log::info!("end adv block");
+        self.update_metrics(|m| m.ad_blocks_played += 1);
     }
 
-    fn play_media_blocking(&self, path: &Path) {
-        self.play_media_non_blocking(path);
+    /// Plays media file till the end, returns false if playback was interrupted
+    /// by content with greater priority or cancelled by operator
+    fn play_media_blocking(
+        &mut self,
+        path: &Path,
+        priority: Priority,
+        content_type: ContentType,
+    ) -> bool {
+        if let Err(e) = self.play_media_non_blocking(path, content_type) {
+            // skip broken file
+            self.handle_media_error(e);
+            return true;
+        }
         loop {
-            wait_seconds(1);
+            let deadline = self.get_playback_deadline();
+            match self.wait_signal(Output::Main, deadline) {
+                Some(Signal::MediaEnded(_)) => return true,
+                Some(Signal::MediaError(_)) => {
+                    self.handle_media_error(Error::MediaPlay {
+                        path: self.resolve_media_path(path),
+                        reason: "playback error".to_string(),
+                    });
+                    return true;
+                }
+                _ => {}
+            }
             if !self.media_player.is_playing() {
-                break;
+                return true;
+            }
+
+            if self.is_playback_stuck() {
+                self.media_player.stop();
+                self.handle_media_error(Error::MediaStalled {
+                    path: self.resolve_media_path(path),
+                    reason: "playback is stuck".to_string(),
+                });
+                return true;
+            }
+
+            if self.has_preempting_content(priority) {
+                self.fade_out();
+                return false;
+            }
+
+            if priority.0 >= Layer::OperatorMessage && self.cancel_requested {
+                self.cancel_requested = false;
+                self.media_player.stop();
+                return false;
+            }
+        }
+    }
+
+    /// Applies signals received so far without waiting, playback signals are kept for later
+    fn receive_control_commands(&mut self) {
+        while let Ok(signal) = self.signals.try_recv() {
+            self.apply_signal(&signal);
+            if signal.output().is_some() {
+                self.deferred_signals.push_back(signal);
             }
         }
     }
 
-    fn play_media_non_blocking(&self, path: &Path) {
+    /// Waits until given instant for playback signal of given output, control command or silence
+    /// analysis result; playback signals of other output are kept for later
+    fn wait_signal(&mut self, output: Output, deadline: Instant) -> Option<Signal> {
+        if let Some(index) = self
+            .deferred_signals
+            .iter()
+            .position(|s| s.output() == Some(output))
+        {
+            return self.deferred_signals.remove(index);
+        }
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let signal = self.signals.recv_timeout(timeout).ok()?;
+            self.apply_signal(&signal);
+            match signal.output() {
+                Some(o) if o != output => self.deferred_signals.push_back(signal),
+                _ => return Some(signal),
+            }
+        }
+    }
+
+    /// Waits for given time, control commands are applied at once, playback signals are kept for later
+    fn sleep(&mut self, duration: StdDuration) {
+        let deadline = Instant::now() + duration;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let Ok(signal) = self.signals.recv_timeout(timeout) else {
+                return;
+            };
+            self.apply_signal(&signal);
+            if signal.output().is_some() {
+                self.deferred_signals.push_back(signal);
+            }
+        }
+    }
+
+    /// Waits until schedule change, control command, playback signal or dead air timeout
+    fn wait_idle(&mut self) {
+        let mut deadline = Instant::now() + IDLE_RECHECK_INTERVAL;
+        if let Some(dead_air) = self.node_config.dead_air.as_ref() {
+            if !self.dead_air_alert {
+                deadline =
+                    deadline.min(self.last_playback + StdDuration::from_secs(dead_air.timeout));
+            }
+        }
+        let deadline = self.get_schedule_deadline(deadline);
+        self.wait_signal(Output::Main, deadline);
+    }
+
+    /// Moves control commands to pending messages, keeps silence analysis result of current track
+    fn apply_signal(&mut self, signal: &Signal) {
+        match signal {
+            Signal::Command(ControlCommand::Message(layer, file)) => {
+                log::info!("received {:?} message {:?}", layer, file);
+                self.pending_messages.push(Message {
+                    layer: *layer,
+                    file: file.clone(),
+                });
+            }
+            Signal::Command(ControlCommand::Cancel) => {
+                log::info!("received cancel");
+                self.pending_messages.clear();
+                self.cancel_requested = true;
+            }
+            Signal::SilenceFound(path, intervals) => {
+                let current_track = self
+                    .current_track
+                    .as_ref()
+                    .map(|t| self.resolve_media_path(t));
+                if current_track.as_ref() == Some(path) {
+                    self.track_silence = Some(intervals.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Drops playback signals of given output left from previous media
+    fn drop_playback_signals(&mut self, output: Output) {
+        self.receive_control_commands();
+        self.deferred_signals.retain(|s| s.output() != Some(output));
+    }
+
+    /// Returns the nearest of given instant and next schedule change
+    fn get_schedule_deadline(&self, deadline: Instant) -> Instant {
+        let dt = Local::now().naive_local();
+        let next_dt = self
+            .playlist
+            .as_ref()
+            .and_then(|pl| pl.get_next_schedule_datetime(dt));
+        match next_dt.and_then(|next_dt| (next_dt - dt).to_std().ok()) {
+            Some(d) => deadline.min(Instant::now() + d + SCHEDULE_TIMER_MARGIN),
+            None => deadline,
+        }
+    }
+
+    /// Returns instant of the nearest check while media is playing: schedule change or stuck playback check
+    fn get_playback_deadline(&self) -> Instant {
+        let mut deadline = Instant::now() + IDLE_RECHECK_INTERVAL;
+        if let Some(dead_air) = self.node_config.dead_air.as_ref() {
+            deadline = deadline
+                .min(self.last_playback_time_change + StdDuration::from_secs(dead_air.timeout));
+        }
+        self.get_schedule_deadline(deadline)
+    }
+
+    /// Returns instant of the nearest music check: playback deadline, volume ramp step,
+    /// state save or silence inside track
+    fn get_music_deadline(&self) -> Instant {
+        let mut deadline = self
+            .get_playback_deadline()
+            .min(self.last_state_save + STATE_SAVE_INTERVAL);
+        if self.is_music_volume_ramping() {
+            deadline = deadline.min(Instant::now() + StdDuration::from_secs(1));
+        }
+        if let (Some(intervals), Some(time)) =
+            (self.track_silence.as_ref(), self.media_player.get_time())
+        {
+            if let Some(interval) = intervals
+                .iter()
+                .find(|i| i.start > time + SILENCE_SKIP_TOLERANCE_MS)
+            {
+                let wait = StdDuration::from_millis((interval.start - time) as u64);
+                deadline = deadline.min(Instant::now() + wait);
+            }
+        }
+        deadline
+    }
+
+    /// Removes and returns the earliest pending message from the highest layer
+    fn take_pending_message(&mut self) -> Option<Message> {
+        let top_layer = self.pending_messages.iter().map(|m| m.layer).max()?;
+        let index = self
+            .pending_messages
+            .iter()
+            .position(|m| m.layer == top_layer)?;
+        Some(self.pending_messages.remove(index))
+    }
+
+    /// Removes and returns pending operator message if it must be played over ducked music
+    fn take_voice_over_message(&mut self) -> Option<Message> {
+        self.receive_control_commands();
+        let ducked = self
+            .playlist
+            .as_ref()
+            .is_some_and(|pl| pl.is_operator_message_ducked());
+        let top_layer = self.pending_messages.iter().map(|m| m.layer).max();
+        if ducked && top_layer == Some(Layer::OperatorMessage) {
+            return self.take_pending_message();
+        }
+        None
+    }
+
+    /// Returns true if there is pending message or due event with priority greater than given one
+    fn has_preempting_content(&mut self, priority: Priority) -> bool {
+        self.receive_control_commands();
+        if self
+            .pending_messages
+            .iter()
+            .any(|m| Priority(m.layer, 0) > priority)
+        {
+            return true;
+        }
+
+        if let Some(pl) = self.playlist.as_ref() {
+            let dt = Local::now().naive_local();
+            return self.get_due_event(pl, dt, priority).is_some();
+        }
+        false
+    }
+
+    /// Returns not played event occurrence with the highest priority, which is greater than given one
+    fn get_due_event(
+        &self,
+        pl: &Playlist,
+        dt: NaiveDateTime,
+        priority: Priority,
+    ) -> Option<EventOccurrence> {
+        pl.get_events_for_datetime(dt, &self.node_config.node)
+            .into_iter()
+            .filter(|o| {
+                Priority(Layer::ScheduledContent, o.priority()) > priority
+                    && !self.events_journal.is_played(o)
+            })
+            .max_by_key(|o| o.priority())
+    }
+
+    /// Returns event occurrence which was playing when node was stopped, if it is still in playlist
+    fn take_restored_event(&mut self, pl: &Playlist) -> Option<EventOccurrence> {
+        let pending = self.restored_event.take()?;
+        let event = pl.events.as_ref()?.get(&pending.id)?;
+        Some(EventOccurrence {
+            id: pending.id,
+            datetime: pending.datetime,
+            event: event.clone(),
+        })
+    }
+
+    /// Returns random track index, recently played tracks are avoided while possible
+    fn get_random_track_index(&mut self, music_files: &[PathBuf]) -> usize {
+        let not_played: Vec<usize> = (0..music_files.len())
+            .filter(|i| !self.history.contains(&music_files[*i]))
+            .collect();
+        match not_played.choose(&mut self.random_generator) {
+            Some(i) => *i,
+            None => self.random_generator.gen_range(0..music_files.len()),
+        }
+    }
+
+    /// Saves current track and position, music order, history, last slots and pending content
+    /// if any of them changed
+    fn save_state(&mut self) {
+        self.last_state_save = Instant::now();
+        // music order is kept while other content plays
+        let (music_files, next_track_index) = match self.restored_music_order.as_ref() {
+            // music was not started since restart
+            Some(restored) => restored.clone(),
+            None => (self.music_files.clone(), self.next_track_index),
+        };
+        let (current_track, position) = match (&self.status, self.media_player.is_playing()) {
+            (PlayerState::MusicPlaying(_), true) => {
+                (self.current_track.clone(), self.media_player.get_time())
+            }
+            _ => self.interrupted_track.clone().unzip(),
+        };
+
+        self.state_store.save(&SavedState {
+            saved_at: None,
+            current_track,
+            position,
+            next_track_index,
+            music_files,
+            history: self.history.clone(),
+            last_advertizement_slot: self.last_advertizement_slot,
+            last_time_announcement: self.last_time_announcement,
+            interrupted_advertizement: self.interrupted_advertizement.clone(),
+            pending_event: self.pending_event.clone().or(self.restored_event.clone()),
+        });
+    }
+
+    /// Returns media files for event: single file, folder content or synthesized speech
+    fn load_event_media_files(&self, occurrence: &EventOccurrence) -> Vec<PathBuf> {
+        let event = &occurrence.event;
+        if let Some(file) = event.file.as_ref() {
+            return vec![PathBuf::from(file)];
+        }
+        if let Some(folder) = event.folder.as_ref() {
+            let media_folder = &self.node_config.media.folder;
+            return load_media_files_list_from_dir(Path::new(media_folder).join(folder).as_path())
+                .into_iter()
+                .filter(|p| !self.quarantine.is_quarantined(p))
+                .collect();
+        }
+        if let Some(text) = event.text.as_ref() {
+            if let Some(file) = tts::synthesize(self.node_config, text) {
+                return vec![file];
+            }
+        }
+        log::error!("event {} has no playable content", occurrence.id);
+        vec![]
+    }
+
+    fn play_media_non_blocking(&mut self, path: &Path, content_type: ContentType) -> Result<()> {
+        self.play_media_non_blocking_on(Output::Main, path, content_type, None)
+    }
+
+    /// Starts media on given output at given volume, at effective volume of content type by default
+    fn play_media_non_blocking_on(
+        &mut self,
+        output: Output,
+        path: &Path,
+        content_type: ContentType,
+        start_volume: Option<i32>,
+    ) -> Result<()> {
+        let path = self.resolve_media_path(path);
+        let path = path.as_path();
+
+        if self.quarantine.is_quarantined(path) {
+            return Err(Error::MediaQuarantined(path.to_path_buf()));
+        }
+        if fs::metadata(path).is_ok_and(|m| m.len() == 0) {
+            return Err(Error::MediaPlay {
+                path: path.to_path_buf(),
+                reason: "zero-length file".to_string(),
+            });
+        }
+
+        let media = vlc::Media::new_path(&self.vlc_instance, path)
+            .ok_or_else(|| Error::MediaOpen(path.to_path_buf()))?;
+
+        let volume = self.get_volume(content_type);
+        log::info!(
+            file:? = path, content:? = content_type, volume = volume;
+            "effective volume {} for {:?}", volume, content_type
+        );
+        self.update_shared_status(|status| {
+            status.now_playing = Some(path.to_path_buf());
+            status.volume = volume;
+        });
+
+        self.drop_playback_signals(output);
+        let media_player = self.get_output_player(output);
+        media_player.set_media(&media);
+        set_volume(media_player, start_volume.unwrap_or(volume));
+        media_player.play().map_err(|_| Error::MediaPlay {
+            path: path.to_path_buf(),
+            reason: "playback cannot be started".to_string(),
+        })?;
+        self.wait_for_playback(output, path)?;
+        self.mark_playback();
+        Ok(())
+    }
+
+    /// Waits until media starts playing, decoder errors and immediate stops are treated as failures
+    fn wait_for_playback(&mut self, output: Output, path: &Path) -> Result<()> {
+        let deadline = Instant::now() + PLAYBACK_START_TIMEOUT;
+        loop {
+            let reason = match self.wait_signal(output, deadline) {
+                Some(Signal::MediaPlaying(_)) => return Ok(()),
+                Some(Signal::MediaError(_)) => "decoder error",
+                Some(Signal::MediaEnded(_)) => "playback stopped immediately",
+                Some(_) => continue,
+                None => {
+                    return Err(Error::MediaStalled {
+                        path: path.to_path_buf(),
+                        reason: "playback start timeout".to_string(),
+                    })
+                }
+            };
+            return Err(Error::MediaPlay {
+                path: path.to_path_buf(),
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    fn get_output_player(&self, output: Output) -> &vlc::MediaPlayer {
+        match output {
+            Output::Main => &self.media_player,
+            Output::Voice => &self.voice_player,
+        }
+    }
+
+    /// Starts fallback content if nothing was played for configured time during working hours
+    fn watch_dead_air(&mut self) {
+        if self.media_player.is_playing() || self.voice_player.is_playing() {
+            self.mark_playback();
+            return;
+        }
+        let Some(dead_air) = self.node_config.dead_air.as_ref() else {
+            return;
+        };
+
+        // silence outside working hours is expected
+        let dt = Local::now().naive_local();
+        if !self
+            .playlist
+            .as_ref()
+            .is_some_and(|pl| pl.is_working_time(dt))
+        {
+            self.last_playback = Instant::now();
+            self.dead_air_alert = false;
+            return;
+        }
+
+        // next fallback file is started at once while alert is active
+        if !self.dead_air_alert
+            && self.last_playback.elapsed() < StdDuration::from_secs(dead_air.timeout)
+        {
+            return;
+        }
+        if !self.dead_air_alert {
+            log::error!(
+                target: "alert",
+                "alert: dead air for {} seconds during working hours",
+                self.last_playback.elapsed().as_secs()
+            );
+            self.dead_air_alert = true;
+        }
+
+        let Some(fallback_folder) = dead_air.fallback_folder.as_ref() else {
+            return;
+        };
+        let media_folder = &self.node_config.media.folder;
+        let fallback_files: Vec<PathBuf> =
+            load_media_files_list_from_dir(Path::new(media_folder).join(fallback_folder).as_path())
+                .into_iter()
+                .filter(|p| !self.quarantine.is_quarantined(p))
+                .collect();
+        let Some(fallback_file) = fallback_files.choose(&mut self.random_generator).cloned() else {
+            log::error!(target: "alert", "alert: fallback folder {:?} is empty", fallback_folder);
+            return;
+        };
+
+        log::warn!("start fallback {:?}", fallback_file);
+        if let Err(e) = self.play_media_non_blocking(&fallback_file, ContentType::Music) {
+            self.handle_media_error(e);
+        }
+    }
+
+    /// Returns true if media player is playing, but playback position does not change for dead air timeout
+    fn is_playback_stuck(&mut self) -> bool {
+        self.mark_playback();
+        let Some(dead_air) = self.node_config.dead_air.as_ref() else {
+            return false;
+        };
+
+        if self.dead_air_alert {
+            log::info!("dead air is over");
+            self.dead_air_alert = false;
+        }
+
+        let time = self.media_player.get_time();
+        if time != self.last_playback_time {
+            self.last_playback_time = time;
+            self.last_playback_time_change = Instant::now();
+            return false;
+        }
+        if self.last_playback_time_change.elapsed() < StdDuration::from_secs(dead_air.timeout) {
+            return false;
+        }
+        log::error!(target: "alert", "alert: playback is stuck at {:?} ms", time);
+        self.last_playback_time_change = Instant::now();
+        true
+    }
+
+    /// Starts background search of long silence inside music track (if enabled)
+    fn start_silence_analysis(&mut self, path: &Path) {
+        self.track_silence = None;
+        if let Some(silence) = self.node_config.dead_air.as_ref().and_then(|d| d.silence) {
+            let sender = self.signal_sender.clone();
+            silence::spawn_analysis(
+                self.resolve_media_path(path),
+                (silence * 1000) as i64,
+                move |path, intervals| {
+                    let _ = sender.send(Signal::SilenceFound(path, intervals));
+                },
+            );
+        }
+    }
+
+    /// Skips long silence inside music track found by background analysis
+    fn skip_track_silence(&mut self) {
+        let (Some(intervals), Some(time)) =
+            (self.track_silence.as_ref(), self.media_player.get_time())
+        else {
+            return;
+        };
+        if let Some(interval) = intervals
+            .iter()
+            .find(|i| time + SILENCE_SKIP_TOLERANCE_MS >= i.start && time < i.end)
+        {
+            log::error!(
+                target: "alert",
+                "alert: {:?} has {} seconds of silence at {} ms, skipping",
+                self.current_track,
+                (interval.end - interval.start) / 1000,
+                interval.start
+            );
+            self.media_player.set_time(interval.end);
+        }
+    }
+
+    /// Returns path inside media folder for relative paths
+    fn resolve_media_path(&self, path: &Path) -> PathBuf {
         let media_folder = self.node_config.media.folder.as_str();
-        let media: Media;
+        if path.starts_with(media_folder) {
+            return path.to_path_buf();
+        }
+        Path::new(media_folder).join(path)
+    }
+
+    /// Logs media error, file which cannot be opened or decoded is moved to quarantine,
+    /// stalled playback is transient, so such file is only skipped
+    fn handle_media_error(&mut self, e: Error) {
+        e.log();
+        self.record_error(&e);
+        self.update_metrics(|m| m.playback_failures += 1);
+        let reason = e.to_string();
+        match e {
+            Error::MediaOpen(path) | Error::MediaPlay { path, .. } => {
+                self.quarantine.add(&path, &reason);
+                let report = self.quarantine.report();
+                self.update_shared_status(|status| status.quarantine = report);
+            }
+            _ => {}
+        }
+    }
 
-        if !path.starts_with(media_folder) {
-            media = vlc::Media::new_path(
-                &self.vlc_instance,
-                &Path::join(Path::new(media_folder), path),
-            )
-            .unwrap();
-        } else {
-            media = vlc::Media::new_path(&self.vlc_instance, path).unwrap();
+    /// Reports media files which do not match integrity manifest, changed files are moved to quarantine
+    fn check_integrity(&mut self) {
+        let media_folder = PathBuf::from(&self.node_config.media.folder);
+        let quarantine_enabled = self
+            .node_config
+            .integrity
+            .as_ref()
+            .and_then(|i| i.quarantine)
+            .unwrap_or(true);
+        let problems = self.integrity.check(&media_folder);
+        for problem in problems.iter() {
+            let e = Error::MediaIntegrity {
+                path: problem.path.clone(),
+                reason: problem.reason.clone(),
+            };
+            e.log();
+            self.record_error(&e);
+            if quarantine_enabled && problem.path.exists() {
+                self.quarantine.add(&problem.path, &e.to_string());
+            }
+        }
+        if !problems.is_empty() {
+            let report = self.quarantine.report();
+            self.update_shared_status(|status| status.quarantine = report);
         }
+    }
 
-        self.media_player.set_media(&media);
-        self.media_player.set_volume(100).unwrap();
-        self.media_player.play().unwrap();
+    /// Returns effective volume for given content type at current time
+    fn get_volume(&self, content_type: ContentType) -> i32 {
+        self.playlist
+            .as_ref()
+            .map_or(playlist::DEFAULT_VOLUME_LEVEL, |pl| {
+                pl.get_volume(Local::now().naive_local(), content_type)
+            })
     }
 
-    fn fade_out(&self) {
-        if !self.media_player.is_playing() {
+    fn is_music_volume_ramping(&self) -> bool {
+        self.playlist.as_ref().is_some_and(|pl| {
+            pl.get_volume(Local::now().naive_local(), ContentType::Music)
+                != self.media_player.get_volume()
+        })
+    }
+
+    /// Smoothly moves music volume to the scheduled level, one step per second
+    fn ramp_music_volume(&self) {
+        let Some(pl) = self.playlist.as_ref() else {
+            return;
+        };
+        let target = pl.get_volume(Local::now().naive_local(), ContentType::Music);
+        let current = self.media_player.get_volume();
+        if current == target {
+            return;
+        }
+
+        let step = pl.get_volume_ramp_step();
+        let volume = current + (target - current).clamp(-step, step);
+        set_volume(&self.media_player, volume);
+        self.update_shared_status(|status| status.volume = volume);
+        if volume == target {
+            log::info!("effective volume {} for {:?}", volume, ContentType::Music);
+        }
+    }
+
+    fn update_shared_status(&self, update: impl FnOnce(&mut NodeStatus)) {
+        if let Ok(mut status) = self.shared_status.lock() {
+            update(&mut status);
+        }
+    }
+
+    /// Keeps error in the latest errors list of node status
+    fn record_error(&self, e: &Error) {
+        let entry = format!(
+            "{} {}: {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            e.category(),
+            e
+        );
+        self.update_shared_status(|status| {
+            status.recent_errors.push(entry);
+            let excess = status
+                .recent_errors
+                .len()
+                .saturating_sub(RECENT_ERRORS_COUNT);
+            status.recent_errors.drain(..excess);
+        });
+    }
+
+    fn update_metrics(&self, update: impl FnOnce(&mut Metrics)) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            update(&mut metrics);
+        }
+    }
+
+    fn mark_playback(&mut self) {
+        self.last_playback = Instant::now();
+        self.update_metrics(|m| m.last_playback = Some(Instant::now()));
+    }
+
+    /// Counts passed advertizement slots of working time, which were not started
+    fn count_missed_ad_slots(&mut self) {
+        let dt = Local::now().naive_local();
+        let Some(pl) = self.playlist.as_ref() else {
+            self.last_ad_slots_check = dt;
             return;
+        };
+
+        // slot is missed when its minute is over
+        let mut missed = 0;
+        let mut slot = self.last_ad_slots_check.with_second(0).unwrap_or(dt) + Duration::minutes(1);
+        let current_slot = dt.with_second(0).unwrap_or(dt);
+        while slot < current_slot {
+            if pl.is_working_time(slot)
+                && !pl.get_advertizement_folders_for_datetime(slot).is_empty()
+                && self.last_advertizement_slot != Some(slot)
+            {
+                log::warn!(slot:% = slot; "adv slot {} missed", slot);
+                missed += 1;
+            }
+            slot += Duration::minutes(1);
+        }
+        self.last_ad_slots_check = current_slot - Duration::minutes(1);
+        if missed > 0 {
+            self.update_metrics(|m| m.ad_blocks_missed += missed);
         }
-        for vol in (0..100).step_by(10).rev() {
-            self.media_player.set_volume(vol).unwrap();
-            thread::sleep(::std::time::Duration::from_millis(500));
+    }
+
+    /// Plays voice message on separate player over ducked music, returns false if playback
+    /// was interrupted by content with greater priority
+    fn play_voice_over(
+        &mut self,
+        path: &Path,
+        priority: Priority,
+        content_type: ContentType,
+    ) -> bool {
+        let level = self
+            .playlist
+            .as_ref()
+            .map_or(playlist::DEFAULT_DUCKING_LEVEL, |pl| pl.get_ducking_level());
+        let music_volume = self.get_volume(ContentType::Music);
+
+        log::info!(state = "VoiceOver", file:? = path; "start voice over {:?}", path);
+        self.fade(Output::Main, music_volume * level / 100);
+        if let Err(e) = self.play_media_non_blocking_on(Output::Voice, path, content_type, None) {
+            // skip broken file
+            self.handle_media_error(e);
+            self.fade(Output::Main, music_volume);
+            return true;
         }
+        loop {
+            let deadline = self.get_playback_deadline();
+            match self.wait_signal(Output::Voice, deadline) {
+                Some(Signal::MediaEnded(_)) => break,
+                Some(Signal::MediaError(_)) => {
+                    self.handle_media_error(Error::MediaPlay {
+                        path: self.resolve_media_path(path),
+                        reason: "playback error".to_string(),
+                    });
+                    break;
+                }
+                _ => {}
+            }
+            if !self.voice_player.is_playing() {
+                break;
+            }
+
+            if priority.0 >= Layer::OperatorMessage && self.cancel_requested {
+                self.cancel_requested = false;
+                self.voice_player.stop();
+                break;
+            }
+
+            // music is faded out by preempting content
+            if self.has_preempting_content(priority) {
+                self.voice_player.stop();
+                return false;
+            }
+        }
+        self.fade(Output::Main, music_volume);
+        true
+    }
+
+    /// Fades out music track, remembers track and its position for resuming (if enabled)
+    fn interrupt_music(&mut self) {
+        self.fade_out();
+
+        let threshold = self
+            .playlist
+            .as_ref()
+            .and_then(|pl| pl.get_interrupted_track_resume_threshold());
+        let Some(threshold) = threshold else {
+            return;
+        };
+
+        let time = self.media_player.get_time();
+        let duration = self.media_player.get_media().and_then(|m| m.duration());
+        if let (Some(track), Some(time), Some(duration)) =
+            (self.current_track.clone(), time, duration)
+        {
+            if duration - time >= threshold.num_milliseconds() {
+                self.interrupted_track = Some((track, time));
+            } else {
+                log::info!("{:?} is almost over and won't be resumed", track);
+            }
+        }
+    }
+
+    /// Plays media file from given time (in milliseconds) with fade in
+    fn resume_media(&mut self, path: &Path, time: i64) {
+        // media is started muted, so its beginning is not heard before the resume point
+        if let Err(e) =
+            self.play_media_non_blocking_on(Output::Main, path, ContentType::Music, Some(0))
+        {
+            self.handle_media_error(e);
+            return;
+        }
+        // time can be set only when media is playing, playback start is already awaited
+        self.media_player.set_time(time);
+        self.fade_in();
+    }
+
+    fn fade_in(&mut self) {
+        self.fade(Output::Main, self.get_volume(ContentType::Music));
+    }
+
+    fn fade_out(&mut self) {
+        if !self.media_player.is_playing() {
+            return;
+        }
+        self.fade(Output::Main, 0);
         self.media_player.pause();
     }
 
-    fn read_playlist(&self) -> Playlist {
-        let mut playlist = playlist::Playlist::read_from_config(self.node_config);
+    /// Changes output volume from current to given one using playlist fade settings,
+    /// control commands are received meanwhile
+    fn fade(&mut self, output: Output, to: i32) {
+        let fade = self
+            .playlist
+            .as_ref()
+            .map(|pl| pl.get_fade_settings())
+            .unwrap_or_default();
+
+        let from = self.get_output_player(output).get_volume();
+        let step_duration = StdDuration::from_millis(fade.duration / fade.steps as u64);
+        for step in 1..=fade.steps {
+            let progress = step as f64 / fade.steps as f64;
+            set_volume(
+                self.get_output_player(output),
+                get_fade_step_volume(from, to, progress, fade.curve),
+            );
+            self.sleep(step_duration);
+        }
+    }
+
+    /// Returns playlist, waits while it can't be read; None if message is received meanwhile
+    fn read_playlist(&mut self) -> Option<Playlist> {
+        let mut last_error: Option<String> = None;
 
         // wait for playlist
-        while playlist.is_none() {
-            playlist = playlist::Playlist::read_from_config(self.node_config);
-            wait_seconds(1);
+        loop {
+            self.watch_dead_air();
+            let (result, rollout) = self.playlist_loader.load(self.node_config);
+            self.report_rollout(&rollout);
+            match result {
+                Ok(playlist) => {
+                    self.update_metrics(|m| m.playlist_reloads += 1);
+                    let hash = playlist.get_hash();
+                    self.update_shared_status(|status| status.playlist_hash = Some(hash));
+                    return Some(playlist);
+                }
+                Err(e) => {
+                    self.update_metrics(|m| m.playlist_errors += 1);
+                    // log each distinct error once
+                    if last_error.as_ref() != Some(&e.to_string()) {
+                        e.log();
+                        self.record_error(&e);
+                        last_error = Some(e.to_string());
+                    }
+                }
+            }
+            self.sleep(RETRY_INTERVAL);
+            if !self.pending_messages.is_empty() {
+                return None;
+            }
+        }
+    }
+
+    /// Logs rollout status changes and publishes status for control channel and heartbeats
+    fn report_rollout(&self, rollout: &RolloutStatus) {
+        let changed = self
+            .shared_status
+            .lock()
+            .map_or(true, |s| s.rollout.as_ref() != Some(rollout));
+        if !changed {
+            return;
+        }
+        if let (true, Some(version)) = (rollout.applied, rollout.active.as_ref()) {
+            log::info!("playlist version {} applied", version);
         }
-        return playlist.unwrap();
+        if rollout.rolled_back {
+            let e = Error::Rollout(format!(
+                "playlist {} is not applied, rolled back to {}: {}",
+                rollout::describe_version(rollout.target.as_deref()),
+                rollout::describe_version(rollout.active.as_deref()),
+                rollout.error.as_deref().unwrap_or_default()
+            ));
+            e.log();
+            self.record_error(&e);
+            self.update_metrics(|m| m.playlist_errors += 1);
+        }
+        let rollout = rollout.clone();
+        self.update_shared_status(|status| status.rollout = Some(rollout));
     }
 
-    pub fn load_media_files_list_from_dirs(&self, dirs: &Vec<String>) -> Vec<PathBuf> {
+    /// Returns media files of given folders, waits while there are none; empty list if message
+    /// is received meanwhile
+    pub fn load_media_files_list_from_dirs(&mut self, dirs: &Vec<String>) -> Vec<PathBuf> {
         let media_folder = &self.node_config.media.folder;
         let mut media_list: Vec<PathBuf> = Vec::new();
 
         // wait for media files
         loop {
+            self.watch_dead_air();
             for dir in dirs {
-                media_list.extend(load_media_files_list_from_dir(
-                    Path::new(media_folder).join(dir).as_path(),
-                ))
+                let files: Vec<PathBuf> =
+                    load_media_files_list_from_dir(Path::new(media_folder).join(dir).as_path())
+                        .into_iter()
+                        .filter(|p| !self.quarantine.is_quarantined(p))
+                        .collect();
+                let count = files.len();
+                self.update_shared_status(|status| {
+                    status.library.insert(dir.clone(), count);
+                });
+                media_list.extend(files);
             }
-            if media_list.len() == 0 {
-                wait_seconds(1);
-            } else {
+            if !media_list.is_empty() {
+                break;
+            }
+            self.sleep(RETRY_INTERVAL);
+            if !self.pending_messages.is_empty() {
                 break;
             }
         }
@@ -245,26 +1522,60 @@ impl Player<'_> {
     }
 }
 
-fn wait_seconds(seconds: u64) {
-    thread::sleep(std::time::Duration::from_secs(seconds));
+/// Returns index of next track inside music list of given length, the list is played
+/// from the beginning if it became shorter than index
+fn get_track_index(index: usize, count: usize) -> usize {
+    if index < count {
+        index
+    } else {
+        0
+    }
+}
+
+/// Returns volume for fade step, progress is in (0, 1] range
+fn get_fade_step_volume(from: i32, to: i32, progress: f64, curve: FadeCurve) -> i32 {
+    if progress >= 1.0 {
+        return to;
+    }
+    match curve {
+        FadeCurve::Linear => from + ((to - from) as f64 * progress).round() as i32,
+        FadeCurve::Log => {
+            // volume is changed linearly in decibels, silence is treated as -40 dB
+            let to_db = |v: i32| {
+                if v <= 0 {
+                    -40.0
+                } else {
+                    20.0 * (v as f64 / 100.0).log10()
+                }
+            };
+            let db = to_db(from) + (to_db(to) - to_db(from)) * progress;
+            (100.0 * 10f64.powf(db / 20.0)).round() as i32
+        }
+    }
+}
+
+fn set_volume(media_player: &vlc::MediaPlayer, volume: i32) {
+    if media_player.set_volume(volume).is_err() {
+        Error::Backend(format!("cannot set volume {}", volume)).log();
+    }
 }
 
 fn get_supported_file_wildcards(path: &Path) -> Vec<String> {
     let supported_file_ext = ["mp3", "ogg", "wav", "wma", "flac", "m4a"];
     let mut supported_file_wildcards: Vec<String> = Vec::new();
     for ext in supported_file_ext.iter() {
-        let path_as_str = path.to_str().unwrap();
+        let path_as_str = path.to_string_lossy();
         supported_file_wildcards.push(format!("{path_as_str}/**/*.{ext}"));
     }
     supported_file_wildcards
 }
 
-fn load_media_files_list_from_dir(path: &Path) -> Vec<PathBuf> {
+pub fn load_media_files_list_from_dir(path: &Path) -> Vec<PathBuf> {
     let mut media_list: Vec<PathBuf> = Vec::new();
 
     let patterns = get_supported_file_wildcards(path);
 
-    let it = patterns.iter().map(|p| glob(&p).unwrap()).flatten();
+    let it = patterns.iter().filter_map(|p| glob(p).ok()).flatten();
     for entry in it {
         match entry {
             Ok(p) => {
@@ -276,3 +1587,29 @@ fn load_media_files_list_from_dir(path: &Path) -> Vec<PathBuf> {
 
     media_list
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_index_stays_inside_shrunk_list() {
+        let cases = [
+            (0, 5, 0),
+            (4, 5, 4),
+            (5, 5, 0),
+            (7, 5, 0),
+            (3, 1, 0),
+            (3, 0, 0),
+        ];
+        for (index, count, expected) in cases {
+            assert_eq!(
+                get_track_index(index, count),
+                expected,
+                "{} of {}",
+                index,
+                count
+            );
+        }
+    }
+}
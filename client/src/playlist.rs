@@ -1,128 +1,283 @@
-use crate::config::NodeConfig;
-use chrono::prelude::*;
-use serde_derive::Deserialize;
+use crate::config::{Node, NodeConfig};
+use crate::error::{self, Error};
+use crate::format::{self, Format};
+use crate::merge::{self, SettingOrigins};
+use crate::playlist_source::PlaylistSource;
+use crate::rollout;
+use crate::schema::{self, UnknownField};
+use chrono::{prelude::*, Duration};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::{fs, path::Path};
+use std::{io, path::Path};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Playlist {
     pub working_hours: Option<WorkingHours>,
     pub music: Option<Music>,
     pub advertizement: Option<Advertizement>,
     pub time_announcement: Option<TimeAnnouncement>,
+    pub working_hours_announcement: Option<WorkingHoursAnnouncement>,
+    pub events: Option<HashMap<String, Event>>,
+    pub fade: Option<Fade>,
+    pub ducking: Option<Ducking>,
+    pub volume: Option<Volume>,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct WorkingHours {
     pub schedule: Option<Vec<WorkingHoursSchedule>>,
     pub exceptions: Option<HashMap<chrono::NaiveDate, WorkingHoursSchedule>>,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct WorkingHoursSchedule(
-    #[serde(with = "toml_datetime_compat")] pub chrono::NaiveTime,
-    #[serde(with = "toml_datetime_compat")] pub chrono::NaiveTime,
+    #[serde(with = "toml_datetime_compat")]
+    #[schemars(with = "chrono::NaiveTime")]
+    pub chrono::NaiveTime,
+    #[serde(with = "toml_datetime_compat")]
+    #[schemars(with = "chrono::NaiveTime")]
+    pub chrono::NaiveTime,
 );
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Music {
     pub shuffle: Option<bool>,
     pub schedule: Option<Vec<MusicSchedule>>,
+    pub resume_interrupted: Option<bool>,
+    pub resume_threshold: Option<u32>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct MusicSchedule(
-    #[serde(with = "toml_datetime_compat")] pub chrono::NaiveDate,
-    #[serde(with = "toml_datetime_compat")] pub chrono::NaiveDate,
-    Vec<String>,
+    #[serde(with = "toml_datetime_compat")]
+    #[schemars(with = "chrono::NaiveDate")]
+    pub chrono::NaiveDate,
+    #[serde(with = "toml_datetime_compat")]
+    #[schemars(with = "chrono::NaiveDate")]
+    pub chrono::NaiveDate,
+    pub Vec<String>,
 );
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Advertizement {
     pub schedule: Option<HashMap<chrono::NaiveTime, Vec<String>>>,
     pub start_jingle: Option<String>,
     pub end_jingle: Option<String>,
+    pub resume_interrupted: Option<bool>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct TimeAnnouncement {
     pub folder: Option<String>,
 }
 
-fn read_playlist_from_file(file_path: &Path) -> Option<Playlist> {
-    let config_file_content = fs::read_to_string(file_path);
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct WorkingHoursAnnouncement {
+    pub opening: Option<String>,
+    pub closing: Option<HashMap<chrono::NaiveTime, String>>,
+}
 
-    if let Ok(c) = config_file_content {
-        let parsed = toml::from_str::<Playlist>(&c);
-        if let Ok(p) = parsed {
-            return Some(p);
-        };
-        return None;
-    }
-    None
-}
-
-fn merge_playlists(first: Option<Playlist>, second: Option<Playlist>) -> Option<Playlist> {
-    if first.is_some() && second.is_none() {
-        return first;
-    } else if first.is_none() && second.is_some() {
-        return second;
-    } else if first.is_some() && second.is_some() {
-        let first = first.unwrap();
-        let second = second.unwrap();
-
-        let mut merged_working_hours: Option<WorkingHours> = None;
-        if first.working_hours.is_some() && second.working_hours.is_none() {
-            merged_working_hours = first.working_hours;
-        } else if first.working_hours.is_none() && second.working_hours.is_some() {
-            merged_working_hours = second.working_hours;
-        } else if first.working_hours.is_some() && second.working_hours.is_some() {
-            let f = first.working_hours.unwrap();
-            let s = second.working_hours.unwrap();
-            merged_working_hours = Some(WorkingHours {
-                schedule: s.schedule.or(f.schedule),
-                exceptions: s.exceptions.or(f.exceptions),
-            })
-        }
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Event {
+    #[serde(with = "toml_datetime_compat", default)]
+    #[schemars(with = "Option<chrono::NaiveDateTime>")]
+    pub at: Option<chrono::NaiveDateTime>,
+    #[serde(with = "toml_datetime_compat", default)]
+    #[schemars(with = "Option<chrono::NaiveTime>")]
+    pub time: Option<chrono::NaiveTime>,
+    pub weekdays: Option<Vec<u32>>,
+    #[serde(with = "toml_datetime_compat", default)]
+    #[schemars(with = "Option<chrono::NaiveDate>")]
+    pub from: Option<chrono::NaiveDate>,
+    #[serde(with = "toml_datetime_compat", default)]
+    #[schemars(with = "Option<chrono::NaiveDate>")]
+    pub to: Option<chrono::NaiveDate>,
+    pub file: Option<String>,
+    pub folder: Option<String>,
+    pub text: Option<String>,
+    pub priority: Option<u8>,
+    pub nodes: Option<Vec<String>>,
+    pub groups: Option<Vec<String>>,
+}
 
-        let mut merged_music: Option<Music> = None;
-        if first.music.is_some() && second.music.is_none() {
-            merged_music = first.music;
-        } else if first.music.is_none() && second.music.is_some() {
-            merged_music = second.music;
-        } else if first.music.is_some() && second.music.is_some() {
-            let f = first.music.unwrap();
-            let s = second.music.unwrap();
-            merged_music = Some(Music {
-                schedule: s.schedule.or(f.schedule),
-                shuffle: s.shuffle.or(f.shuffle),
-            })
-        }
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Fade {
+    pub duration: Option<u64>,
+    pub steps: Option<u32>,
+    pub curve: Option<FadeCurve>,
+}
 
-        let mut merged_advertizement: Option<Advertizement> = None;
-        if first.advertizement.is_some() && second.advertizement.is_none() {
-            merged_advertizement = first.advertizement;
-        } else if first.advertizement.is_none() && second.advertizement.is_some() {
-            merged_advertizement = second.advertizement;
-        } else if first.advertizement.is_some() && second.advertizement.is_some() {
-            let f = first.advertizement.unwrap();
-            let s = second.advertizement.unwrap();
-            merged_advertizement = Some(Advertizement {
-                schedule: s.schedule.or(f.schedule),
-                start_jingle: s.start_jingle.or(f.start_jingle),
-                end_jingle: s.end_jingle.or(f.end_jingle),
-            })
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    Log,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Ducking {
+    pub time_announcement: Option<bool>,
+    pub operator_message: Option<bool>,
+    pub level: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Volume {
+    pub level: Option<i32>,
+    pub schedule: Option<Vec<VolumeSchedule>>,
+    pub offsets: Option<VolumeOffsets>,
+    pub calibration: Option<i32>,
+    pub ramp_step: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct VolumeSchedule {
+    #[serde(with = "toml_datetime_compat")]
+    #[schemars(with = "chrono::NaiveTime")]
+    pub from: chrono::NaiveTime,
+    #[serde(with = "toml_datetime_compat")]
+    #[schemars(with = "chrono::NaiveTime")]
+    pub to: chrono::NaiveTime,
+    pub level: i32,
+    pub weekdays: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct VolumeOffsets {
+    pub music: Option<i32>,
+    pub advertizement: Option<i32>,
+    pub announcement: Option<i32>,
+    pub event: Option<i32>,
+    pub message: Option<i32>,
+}
+
+/// Content type, used to apply volume offsets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentType {
+    Music,
+    Advertizement,
+    Announcement,
+    Event,
+    Message,
+}
+
+/// Volume level if not defined in playlist
+pub const DEFAULT_VOLUME_LEVEL: i32 = 100;
+
+/// Max volume change per second when level changes at schedule boundary
+const DEFAULT_VOLUME_RAMP_STEP: i32 = 2;
+
+/// Fade settings with defaults applied
+#[derive(Debug, Clone, Copy)]
+pub struct FadeSettings {
+    pub duration: u64,
+    pub steps: u32,
+    pub curve: FadeCurve,
+}
+
+impl Default for FadeSettings {
+    fn default() -> Self {
+        FadeSettings {
+            duration: 5000,
+            steps: 10,
+            curve: FadeCurve::Linear,
         }
+    }
+}
 
-        return Some(Playlist {
-            working_hours: merged_working_hours,
-            music: merged_music,
-            advertizement: merged_advertizement,
-            time_announcement: second.time_announcement.or(first.time_announcement),
-        });
+/// Music volume while voice over is playing, if not defined in playlist
+pub const DEFAULT_DUCKING_LEVEL: i32 = 30;
+
+/// Single occurrence of playlist event
+#[derive(Debug, Clone)]
+pub struct EventOccurrence {
+    pub id: String,
+    pub datetime: NaiveDateTime,
+    pub event: Event,
+}
+
+impl EventOccurrence {
+    pub fn priority(&self) -> u8 {
+        self.event.priority.unwrap_or(DEFAULT_EVENT_PRIORITY)
     }
-    None
+}
+
+/// Interrupted music track with less remaining time is not resumed
+const DEFAULT_RESUME_THRESHOLD_SECONDS: u32 = 30;
+
+/// Priority of events without explicit priority, same as advertizement and announcements
+pub const DEFAULT_EVENT_PRIORITY: u8 = 50;
+
+/// Events which are late more than this limit (e.g. node was turned off) are not played
+const EVENT_LATENESS_LIMIT_MINUTES: i64 = 5;
+
+/// Effective playlist setting and document which defines it
+#[derive(Debug, Clone)]
+pub struct ExplainedSetting {
+    pub key: String,
+    pub value: toml::Value,
+    pub origin: PathBuf,
+}
+
+/// Returns playlist documents names without extension in merge order: default, groups and tags
+/// in declared order, node, documents of playlist version are inside `versions/{version}` folder
+fn get_layer_names(node: &Node, version: Option<&str>) -> Vec<String> {
+    let mut names = vec!["playlist".to_string()];
+    for group in node.groups.iter().flatten() {
+        names.push(format!("playlist_group_{}", group));
+    }
+    for tag in node.tags.iter().flatten() {
+        names.push(format!("playlist_tag_{}", tag));
+    }
+    if let Some(node_name) = node.name.as_ref() {
+        names.push(format!("playlist_{}", node_name));
+    }
+    match version {
+        Some(version) => names
+            .into_iter()
+            .map(|name| format!("versions/{}/{}", version, name))
+            .collect(),
+        None => names,
+    }
+}
+
+/// Returns origin and parsed playlist document of the first supported format found, None if there is none
+fn read_layer(
+    source: &PlaylistSource,
+    name: &str,
+) -> error::Result<Option<(PathBuf, toml::Value)>> {
+    for extension in format::EXTENSIONS {
+        let name = format!("{}.{}", name, extension);
+        if let Some(document) = source.read(&name, parse_layer)? {
+            return Ok(Some((source.get_origin(&name), document)));
+        }
+    }
+    Ok(None)
+}
+
+/// Parses playlist document of format given by extension, the document is validated without merge directives
+fn parse_layer(origin: &Path, content: &str) -> error::Result<toml::Value> {
+    let to_error = |source| Error::PlaylistParse {
+        path: origin.to_path_buf(),
+        source,
+    };
+    let format = Format::from_path(origin).unwrap_or(Format::Toml);
+    let document = format::parse(format, content, schema::get_playlist_schema).map_err(to_error)?;
+    let mut stripped = document.clone();
+    merge::strip_directives(&mut stripped);
+    to_playlist(&stripped).map_err(to_error)?;
+    Ok(document)
+}
+
+/// Converts merged document to playlist, document is formatted again because
+/// `toml_datetime_compat` fields can not be deserialized from `toml::Value`
+fn to_playlist(document: &toml::Value) -> Result<Playlist, toml::de::Error> {
+    let content = toml::to_string(document).map_err(serde::de::Error::custom)?;
+    toml::from_str(&content)
 }
 
 fn get_month_day(dt: NaiveDate) -> (u32, u32) {
@@ -130,24 +285,250 @@ fn get_month_day(dt: NaiveDate) -> (u32, u32) {
 }
 
 impl Playlist {
-    /// Returns playlist (if it exists and parseable) for current node
-    pub fn read_from_config(node_config: &NodeConfig) -> Option<Playlist> {
-        let media_folder = node_config.media.folder.as_str();
-        let node_name = &node_config.node.name;
+    /// Returns playlist for current node from `cfg` folder or remote source, version is chosen by rollout
+    pub fn read_from_config(node_config: &NodeConfig) -> error::Result<Playlist> {
+        let source = PlaylistSource::from_config(node_config);
+        let version = rollout::get_target_version(&source, &node_config.node)?;
+        let (playlist, _) = Playlist::read_version(&source, &node_config.node, version.as_deref())?;
+        Ok(playlist)
+    }
 
-        let cfg_folder = Path::new(media_folder).join("cfg");
-        let default_playlist_file_path = cfg_folder.clone().join("playlist.toml");
-        let default_playlist = read_playlist_from_file(&default_playlist_file_path);
+    /// Returns playlist of given version with merged document it was made of: default playlist
+    /// deeply merged with group, tag and node playlists, missing group, tag and node playlists are skipped
+    pub fn read_version(
+        source: &PlaylistSource,
+        node: &Node,
+        version: Option<&str>,
+    ) -> error::Result<(Playlist, toml::Value)> {
+        let layers = get_layer_names(node, version);
 
-        if node_name.is_some() {
-            let node_playlist_file_path = cfg_folder
-                .clone()
-                .join(format!("playlist_{}.toml", node_name.as_ref().unwrap()));
-            let node_playlist = read_playlist_from_file(&node_playlist_file_path);
-            return merge_playlists(default_playlist, node_playlist);
+        let (mut origin, mut merged) =
+            read_layer(source, &layers[0])?.ok_or_else(|| Error::PlaylistRead {
+                path: source.get_origin(&format!("{}.toml", layers[0])),
+                source: io::ErrorKind::NotFound.into(),
+            })?;
+        for name in layers.iter().skip(1) {
+            if let Some((layer_origin, layer)) = read_layer(source, name)? {
+                merge::merge_values(&mut merged, layer);
+                origin = layer_origin;
+            }
         }
 
-        default_playlist
+        merge::strip_directives(&mut merged);
+        let playlist = Playlist::from_document(&merged, &origin)?;
+        Ok((playlist, merged))
+    }
+
+    /// Returns playlist from merged document
+    pub fn from_document(document: &toml::Value, origin: &Path) -> error::Result<Playlist> {
+        to_playlist(document).map_err(|source| Error::PlaylistParse {
+            path: origin.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Returns effective settings with origin of each one, missing layers have no settings
+    pub fn explain(node_config: &NodeConfig) -> error::Result<Vec<ExplainedSetting>> {
+        let source = PlaylistSource::from_config(node_config);
+        let version = rollout::get_target_version(&source, &node_config.node)?;
+
+        let mut origins = SettingOrigins::default();
+        for name in get_layer_names(&node_config.node, version.as_deref()) {
+            if let Some((origin, document)) = read_layer(&source, &name)? {
+                origins.apply(&document, &origin);
+            }
+        }
+        Ok(origins
+            .into_settings()
+            .into_iter()
+            .map(|(key, value, origin)| ExplainedSetting { key, value, origin })
+            .collect())
+    }
+
+    /// Returns keys of playlist documents which are not playlist settings, with documents which contain them
+    pub fn find_unknown_fields(
+        node_config: &NodeConfig,
+    ) -> error::Result<Vec<(PathBuf, UnknownField)>> {
+        let source = PlaylistSource::from_config(node_config);
+        let version = rollout::get_target_version(&source, &node_config.node)?;
+        let schema = schema::get_playlist_schema();
+
+        let mut fields: Vec<(PathBuf, UnknownField)> = Vec::new();
+        for name in get_layer_names(&node_config.node, version.as_deref()) {
+            let Some((origin, mut document)) = read_layer(&source, &name)? else {
+                continue;
+            };
+            merge::strip_directives(&mut document);
+            let content = toml::to_string(&document).unwrap_or_default();
+            if let Ok((_, unknown)) = schema::from_str::<Playlist>(&content, &schema) {
+                fields.extend(unknown.into_iter().map(|f| (origin.clone(), f)));
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Returns SHA-256 of playlist content, keys are sorted so equal playlists have equal hashes
+    pub fn get_hash(&self) -> String {
+        let content = serde_json::to_value(self)
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        format!("{:x}", Sha256::digest(content.as_bytes()))
+    }
+
+    /// Returns list of playlist problems which can't be detected while parsing
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+
+        if let Some(working_hours) = self.working_hours.as_ref() {
+            if let Some(schedule) = working_hours.schedule.as_ref() {
+                if schedule.len() != 7 {
+                    problems.push(format!(
+                        "working_hours.schedule must have 7 entries (monday to sunday), {} found",
+                        schedule.len()
+                    ));
+                }
+                for (i, entry) in schedule.iter().enumerate() {
+                    if entry.0 > entry.1 {
+                        problems.push(format!("working_hours.schedule[{}] begins after end", i));
+                    }
+                }
+            }
+            if let Some(exceptions) = working_hours.exceptions.as_ref() {
+                for (date, entry) in exceptions.iter() {
+                    if entry.0 > entry.1 {
+                        problems.push(format!(
+                            "working_hours.exceptions.{} begins after end",
+                            date
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(schedule) = self.music.as_ref().and_then(|m| m.schedule.as_ref()) {
+            // annual records are compared without year
+            let key = |e: &MusicSchedule| {
+                let annual = e.0.year() == 1970;
+                let from = if annual { get_month_day(e.0) } else { (0, 0) };
+                let to = if annual { get_month_day(e.1) } else { (0, 0) };
+                (annual, from, to)
+            };
+            for (i, entry) in schedule.iter().enumerate() {
+                if entry.0 > entry.1 {
+                    problems.push(format!("music.schedule[{}] begins after end", i));
+                }
+                for (j, other) in schedule.iter().enumerate().skip(i + 1) {
+                    let ((annual, from, to), (other_annual, other_from, other_to)) =
+                        (key(entry), key(other));
+                    let intersect = match (annual, other_annual) {
+                        (true, true) => from <= other_to && other_from <= to,
+                        (false, false) => entry.0 <= other.1 && other.0 <= entry.1,
+                        _ => false,
+                    };
+                    if intersect {
+                        problems.push(format!(
+                            "music.schedule[{}] intersects music.schedule[{}]",
+                            i, j
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(events) = self.events.as_ref() {
+            for (id, event) in events.iter() {
+                if event.at.is_none() && event.time.is_none() {
+                    problems.push(format!("event {} has neither at nor time", id));
+                }
+                if event.file.is_none() && event.folder.is_none() && event.text.is_none() {
+                    problems.push(format!("event {} has neither file, folder nor text", id));
+                }
+                if let Some(weekdays) = event.weekdays.as_ref() {
+                    if weekdays.iter().any(|d| !(1..=7).contains(d)) {
+                        problems.push(format!("event {} weekdays must be in 1..7 range", id));
+                    }
+                }
+            }
+        }
+
+        if let Some(volume) = self.volume.as_ref() {
+            let levels = volume
+                .level
+                .iter()
+                .chain(volume.schedule.iter().flatten().map(|e| &e.level));
+            for level in levels {
+                if !(0..=200).contains(level) {
+                    problems.push(format!("volume level {} must be in 0..200 range", level));
+                }
+            }
+        }
+
+        if self.fade.as_ref().and_then(|f| f.steps) == Some(0) {
+            problems.push("fade.steps must be greater than 0".to_string());
+        }
+
+        problems
+    }
+
+    /// Returns media folders and files referenced by playlist, time announcement files are
+    /// listed for whole hours inside working hours
+    pub fn get_media_references(&self) -> (Vec<String>, Vec<String>) {
+        let mut folders: Vec<String> = Vec::new();
+        let mut files: Vec<String> = Vec::new();
+        if let Some(schedule) = self.music.as_ref().and_then(|m| m.schedule.as_ref()) {
+            folders.extend(schedule.iter().flat_map(|e| e.2.iter().cloned()));
+        }
+        if let Some(advertizement) = self.advertizement.as_ref() {
+            if let Some(schedule) = advertizement.schedule.as_ref() {
+                folders.extend(schedule.values().flatten().cloned());
+            }
+            files.extend(advertizement.start_jingle.iter().cloned());
+            files.extend(advertizement.end_jingle.iter().cloned());
+        }
+        if let Some(folder) = self
+            .time_announcement
+            .as_ref()
+            .and_then(|t| t.folder.as_ref())
+        {
+            let working_hours = self.working_hours.as_ref();
+            let intervals: Vec<&WorkingHoursSchedule> = working_hours
+                .and_then(|w| w.schedule.as_ref())
+                .into_iter()
+                .flatten()
+                .chain(
+                    working_hours
+                        .and_then(|w| w.exceptions.as_ref())
+                        .into_iter()
+                        .flat_map(|e| e.values()),
+                )
+                .collect();
+            for hour in 0..24 {
+                let time = NaiveTime::from_hms_opt(hour, 0, 0).unwrap_or_default();
+                if intervals.iter().any(|i| i.0 <= time && time <= i.1) {
+                    files.push(format!("{}/{:0>2}_00.mp3", folder, hour));
+                }
+            }
+        }
+        if let Some(announcement) = self.working_hours_announcement.as_ref() {
+            files.extend(announcement.opening.iter().cloned());
+            files.extend(
+                announcement
+                    .closing
+                    .iter()
+                    .flat_map(|c| c.values().cloned()),
+            );
+        }
+        if let Some(events) = self.events.as_ref() {
+            for event in events.values() {
+                folders.extend(event.folder.iter().cloned());
+                files.extend(event.file.iter().cloned());
+            }
+        }
+        folders.sort();
+        folders.dedup();
+        files.sort();
+        files.dedup();
+        (folders, files)
     }
 
     /// Returns music folders list for given date
@@ -184,29 +565,86 @@ impl Playlist {
         vec![]
     }
 
-    /// Returns true if given datetime is in working time interval
-    pub fn is_working_time(&self, dt: NaiveDateTime) -> bool {
-        let date_from_dt = dt.date();
-        let weekday = date_from_dt.weekday();
-        let time_from_dt = dt.time();
+    /// Returns working hours interval for given date, exceptions take precedence over weekday schedule
+    pub fn get_working_hours_for_date(&self, dt: NaiveDate) -> Option<&WorkingHoursSchedule> {
+        let weekday = dt.weekday();
 
         if let Some(working_hours) = self.working_hours.as_ref() {
+            if let Some(exceptions) = working_hours.exceptions.as_ref() {
+                if let Some(s) = exceptions.get(&dt) {
+                    return Some(s);
+                };
+            };
             if let Some(schedule) = working_hours.schedule.as_ref() {
                 let day_index = weekday.num_days_from_monday() as usize;
-                if let Some(mut schedule_for_current_day) = schedule.get(day_index) {
-                    if let Some(exceptions) = working_hours.exceptions.as_ref() {
-                        if let Some(s) = exceptions.get(&date_from_dt) {
-                            schedule_for_current_day = s;
-                        };
-                    };
-                    return time_from_dt >= schedule_for_current_day.0
-                        && time_from_dt <= schedule_for_current_day.1;
-                };
+                return schedule.get(day_index);
             };
         };
+        None
+    }
+
+    /// Returns true if given datetime is in working time interval
+    pub fn is_working_time(&self, dt: NaiveDateTime) -> bool {
+        let time_from_dt = dt.time();
+
+        if let Some(schedule_for_current_day) = self.get_working_hours_for_date(dt.date()) {
+            return time_from_dt >= schedule_for_current_day.0
+                && time_from_dt <= schedule_for_current_day.1;
+        };
         false
     }
 
+    /// Returns opening of working day which given datetime belongs to, working day lasts
+    /// until the next opening, so it covers closing after midnight and days off
+    pub fn get_working_day_start(&self, dt: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=7)
+            .filter_map(|days| {
+                let date = dt.date() - Duration::days(days);
+                let working_hours = self.get_working_hours_for_date(date)?;
+                Some(date.and_time(working_hours.0))
+            })
+            .find(|opening| *opening <= dt)
+    }
+
+    /// Returns opening or closing announcement file path if its time is in (from, to] interval
+    pub fn get_working_hours_announcement_file_path(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Option<PathBuf> {
+        let announcement = self.working_hours_announcement.as_ref()?;
+        let in_interval = |dt: NaiveDateTime| dt > from && dt <= to;
+
+        let mut dates = vec![from.date()];
+        if to.date() != from.date() {
+            dates.push(to.date());
+        }
+
+        for date in dates {
+            let Some(working_hours) = self.get_working_hours_for_date(date) else {
+                continue;
+            };
+
+            if let Some(opening) = announcement.opening.as_ref() {
+                if in_interval(date.and_time(working_hours.0)) {
+                    return Some(PathBuf::from(opening));
+                }
+            }
+
+            if let Some(closing) = announcement.closing.as_ref() {
+                let closing_dt = date.and_time(working_hours.1);
+                for (before_closing, file) in closing.iter() {
+                    let offset =
+                        Duration::seconds(before_closing.num_seconds_from_midnight() as i64);
+                    if in_interval(closing_dt - offset) {
+                        return Some(PathBuf::from(file));
+                    }
+                }
+            }
+        }
+        None
+    }
+
     /// Returns advertizement media folders for given datetime
     pub fn get_advertizement_folders_for_datetime(&self, dt: NaiveDateTime) -> Vec<String> {
         if let Some(advertizement) = self.advertizement.as_ref() {
@@ -241,6 +679,65 @@ impl Playlist {
         None
     }
 
+    /// Returns not outdated events occurrences for given datetime and node, event limited by
+    /// nodes and groups lists is returned if node is listed or belongs to listed group
+    pub fn get_events_for_datetime(&self, dt: NaiveDateTime, node: &Node) -> Vec<EventOccurrence> {
+        let mut occurrences: Vec<EventOccurrence> = Vec::new();
+        let oldest_dt = dt - Duration::minutes(EVENT_LATENESS_LIMIT_MINUTES);
+
+        if let Some(events) = self.events.as_ref() {
+            for (id, event) in events.iter() {
+                if event.nodes.is_some() || event.groups.is_some() {
+                    let listed = node
+                        .name
+                        .as_ref()
+                        .is_some_and(|n| event.nodes.iter().flatten().any(|e| e == n));
+                    let member = event.groups.iter().flatten().any(|g| node.is_member_of(g));
+                    if !listed && !member {
+                        continue;
+                    }
+                }
+
+                let mut candidates: Vec<NaiveDateTime> = Vec::new();
+                if let Some(at) = event.at {
+                    candidates.push(at);
+                } else if let Some(time) = event.time {
+                    // occurrence can be late since yesterday
+                    let mut dates = vec![oldest_dt.date()];
+                    if dt.date() != oldest_dt.date() {
+                        dates.push(dt.date());
+                    }
+                    for date in dates {
+                        if event.from.is_some_and(|from| date < from)
+                            || event.to.is_some_and(|to| date > to)
+                        {
+                            continue;
+                        }
+                        if let Some(weekdays) = event.weekdays.as_ref() {
+                            if !weekdays.contains(&date.weekday().number_from_monday()) {
+                                continue;
+                            }
+                        }
+                        candidates.push(date.and_time(time));
+                    }
+                }
+
+                for candidate in candidates {
+                    if candidate > oldest_dt && candidate <= dt {
+                        occurrences.push(EventOccurrence {
+                            id: id.clone(),
+                            datetime: candidate,
+                            event: event.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        occurrences.sort_by_key(|o| o.datetime);
+        occurrences
+    }
+
     pub fn get_advertizement_jingles_file_path(&self) -> (Option<String>, Option<String>) {
         if let Some(advertizement) = self.advertizement.as_ref() {
             return (
@@ -250,4 +747,812 @@ impl Playlist {
         };
         (None, None)
     }
+
+    /// Returns true if advertizement block interrupted by higher priority content must be resumed
+    pub fn is_interrupted_advertizement_resumed(&self) -> bool {
+        self.advertizement
+            .as_ref()
+            .and_then(|a| a.resume_interrupted)
+            .unwrap_or(false)
+    }
+
+    /// Returns minimal remaining time of interrupted music track to resume it, None if resuming is disabled
+    pub fn get_interrupted_track_resume_threshold(&self) -> Option<Duration> {
+        let music = self.music.as_ref()?;
+        if !music.resume_interrupted.unwrap_or(false) {
+            return None;
+        }
+        let threshold = music
+            .resume_threshold
+            .unwrap_or(DEFAULT_RESUME_THRESHOLD_SECONDS);
+        Some(Duration::seconds(threshold as i64))
+    }
+
+    /// Returns fade settings, missing values are replaced with defaults
+    pub fn get_fade_settings(&self) -> FadeSettings {
+        let mut settings = FadeSettings::default();
+        if let Some(fade) = self.fade.as_ref() {
+            settings.duration = fade.duration.unwrap_or(settings.duration);
+            settings.steps = fade.steps.unwrap_or(settings.steps).max(1);
+            settings.curve = fade.curve.unwrap_or(settings.curve);
+        }
+        settings
+    }
+
+    /// Returns true if time announcements are played over ducked music
+    pub fn is_time_announcement_ducked(&self) -> bool {
+        self.ducking
+            .as_ref()
+            .and_then(|d| d.time_announcement)
+            .unwrap_or(false)
+    }
+
+    /// Returns true if operator messages are played over ducked music
+    pub fn is_operator_message_ducked(&self) -> bool {
+        self.ducking
+            .as_ref()
+            .and_then(|d| d.operator_message)
+            .unwrap_or(false)
+    }
+
+    /// Returns music volume while voice over is playing
+    pub fn get_ducking_level(&self) -> i32 {
+        self.ducking
+            .as_ref()
+            .and_then(|d| d.level)
+            .unwrap_or(DEFAULT_DUCKING_LEVEL)
+    }
+
+    /// Returns effective volume for given datetime and content type:
+    /// scheduled level with content type offset and node calibration
+    pub fn get_volume(&self, dt: NaiveDateTime, content_type: ContentType) -> i32 {
+        let Some(volume) = self.volume.as_ref() else {
+            return DEFAULT_VOLUME_LEVEL;
+        };
+
+        let mut level = volume.level.unwrap_or(DEFAULT_VOLUME_LEVEL);
+        if let Some(schedule) = volume.schedule.as_ref() {
+            let weekday = dt.weekday().number_from_monday();
+            let time = dt.time();
+            for entry in schedule.iter() {
+                if let Some(weekdays) = entry.weekdays.as_ref() {
+                    if !weekdays.contains(&weekday) {
+                        continue;
+                    }
+                }
+                // interval can pass midnight
+                let in_interval = if entry.from <= entry.to {
+                    time >= entry.from && time < entry.to
+                } else {
+                    time >= entry.from || time < entry.to
+                };
+                if in_interval {
+                    level = entry.level;
+                    break;
+                }
+            }
+        }
+
+        let offset = volume.offsets.as_ref().and_then(|o| match content_type {
+            ContentType::Music => o.music,
+            ContentType::Advertizement => o.advertizement,
+            ContentType::Announcement => o.announcement,
+            ContentType::Event => o.event,
+            ContentType::Message => o.message,
+        });
+
+        (level + offset.unwrap_or(0) + volume.calibration.unwrap_or(0)).clamp(0, 200)
+    }
+
+    /// Returns the nearest datetime after given one, when scheduled content can start or working
+    /// hours and volume level can change
+    pub fn get_next_schedule_datetime(&self, dt: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut candidates: Vec<NaiveDateTime> = Vec::new();
+        let dates = [dt.date(), dt.date() + Duration::days(1)];
+        let hour_start = dt.date().and_hms_opt(dt.hour(), 0, 0)?;
+
+        // advertizement schedule keys are minutes of every hour
+        if let Some(schedule) = self
+            .advertizement
+            .as_ref()
+            .and_then(|a| a.schedule.as_ref())
+        {
+            for key in schedule.keys() {
+                let slot = hour_start + Duration::minutes(key.minute() as i64);
+                candidates.push(slot);
+                candidates.push(slot + Duration::hours(1));
+            }
+        }
+
+        if self.time_announcement.is_some() {
+            candidates.push(hour_start + Duration::hours(1));
+        }
+
+        for date in dates {
+            let Some(working_hours) = self.get_working_hours_for_date(date) else {
+                continue;
+            };
+            let closing_dt = date.and_time(working_hours.1);
+            candidates.push(date.and_time(working_hours.0));
+            // working time interval includes its end
+            candidates.push(closing_dt + Duration::seconds(1));
+            if let Some(closing) = self
+                .working_hours_announcement
+                .as_ref()
+                .and_then(|a| a.closing.as_ref())
+            {
+                for before_closing in closing.keys() {
+                    candidates.push(
+                        closing_dt
+                            - Duration::seconds(before_closing.num_seconds_from_midnight() as i64),
+                    );
+                }
+            }
+        }
+
+        if let Some(events) = self.events.as_ref() {
+            for event in events.values() {
+                candidates.extend(event.at);
+                if let Some(time) = event.time {
+                    candidates.extend(dates.iter().map(|d| d.and_time(time)));
+                }
+            }
+        }
+
+        if let Some(schedule) = self.volume.as_ref().and_then(|v| v.schedule.as_ref()) {
+            for entry in schedule.iter() {
+                for date in dates {
+                    candidates.push(date.and_time(entry.from));
+                    candidates.push(date.and_time(entry.to));
+                }
+            }
+        }
+
+        candidates.into_iter().filter(|c| *c > dt).min()
+    }
+
+    /// Returns max volume change per second
+    pub fn get_volume_ramp_step(&self) -> i32 {
+        self.volume
+            .as_ref()
+            .and_then(|v| v.ramp_step)
+            .unwrap_or(DEFAULT_VOLUME_RAMP_STEP)
+            .max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, datetime, node, time};
+    use std::fs;
+
+    fn parse_playlist(content: &str) -> Playlist {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn working_hours_announcements_are_played_at_their_time() {
+        let playlist = parse_playlist(
+            r#"
+            [working_hours]
+            schedule = [[09:00:00, 21:00:00], [09:00:00, 21:00:00]]
+            [working_hours.exceptions]
+            2024-12-31 = [10:00:00, 18:00:00]
+
+            [working_hours_announcement]
+            opening = "announcements/opening.mp3"
+            [working_hours_announcement.closing]
+            "00:15:00" = "announcements/closing_15.mp3"
+            "00:00:00" = "announcements/closed.mp3"
+            "#,
+        );
+        // 2024-12-30 is monday, 2024-12-31 is exception
+        let cases = [
+            ("2024-12-30T08:00:00", "2024-12-30T08:59:59", None),
+            (
+                "2024-12-30T08:59:59",
+                "2024-12-30T09:00:00",
+                Some("announcements/opening.mp3"),
+            ),
+            ("2024-12-30T09:00:00", "2024-12-30T09:01:00", None),
+            (
+                "2024-12-30T20:44:00",
+                "2024-12-30T20:45:00",
+                Some("announcements/closing_15.mp3"),
+            ),
+            (
+                "2024-12-30T20:59:00",
+                "2024-12-30T21:00:00",
+                Some("announcements/closed.mp3"),
+            ),
+            ("2024-12-30T21:00:00", "2024-12-30T23:00:00", None),
+            (
+                "2024-12-30T23:00:00",
+                "2024-12-31T10:00:00",
+                Some("announcements/opening.mp3"),
+            ),
+            ("2024-12-31T08:59:00", "2024-12-31T09:00:00", None),
+        ];
+        for (from, to, expected) in cases {
+            assert_eq!(
+                playlist.get_working_hours_announcement_file_path(datetime(from), datetime(to)),
+                expected.map(PathBuf::from),
+                "({}, {}]",
+                from,
+                to
+            );
+        }
+    }
+
+    fn event_ids(playlist: &Playlist, dt: &str, node: &Node) -> Vec<String> {
+        let mut ids: Vec<String> = playlist
+            .get_events_for_datetime(datetime(dt), node)
+            .into_iter()
+            .map(|o| o.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn events_are_played_until_lateness_limit() {
+        let playlist = parse_playlist(
+            r#"
+            [events.sale]
+            time = 12:00:00
+            file = "events/sale.mp3"
+            [events.opening]
+            at = 2024-12-30T09:00:00
+            file = "events/opening.mp3"
+            [events.midnight]
+            time = 23:59:00
+            file = "events/midnight.mp3"
+            "#,
+        );
+        let cases = [
+            ("2024-12-30T11:59:59", vec![]),
+            ("2024-12-30T12:00:00", vec!["sale"]),
+            ("2024-12-30T12:04:59", vec!["sale"]),
+            ("2024-12-30T12:05:00", vec![]),
+            ("2024-12-30T09:04:59", vec!["opening"]),
+            ("2024-12-31T09:04:59", vec![]),
+            // occurrence late since yesterday
+            ("2024-12-31T00:02:00", vec!["midnight"]),
+        ];
+        let node = node("pc101", &[]);
+        for (dt, expected) in cases {
+            assert_eq!(event_ids(&playlist, dt, &node), expected, "{}", dt);
+        }
+    }
+
+    #[test]
+    fn events_are_filtered_by_weekdays_dates_and_nodes() {
+        let playlist = parse_playlist(
+            r#"
+            [events.weekend]
+            time = 10:00:00
+            weekdays = [6, 7]
+            file = "weekend.mp3"
+            [events.january]
+            time = 10:00:00
+            from = 2025-01-01
+            to = 2025-01-31
+            file = "january.mp3"
+            [events.north]
+            time = 10:00:00
+            nodes = ["pc101"]
+            groups = ["north"]
+            file = "north.mp3"
+            "#,
+        );
+        let cases = [
+            // monday
+            ("2024-12-30T10:00:00", node("pc101", &[]), vec!["north"]),
+            ("2024-12-30T10:00:00", node("pc102", &[]), vec![]),
+            (
+                "2024-12-30T10:00:00",
+                node("pc102", &["north"]),
+                vec!["north"],
+            ),
+            // saturday
+            (
+                "2025-01-04T10:00:00",
+                node("pc102", &["south"]),
+                vec!["january", "weekend"],
+            ),
+            ("2025-02-01T10:00:00", node("pc102", &[]), vec!["weekend"]),
+        ];
+        for (dt, node, expected) in cases {
+            assert_eq!(
+                event_ids(&playlist, dt, &node),
+                expected,
+                "{} {:?}",
+                dt,
+                node
+            );
+        }
+    }
+
+    #[test]
+    fn volume_follows_schedule_offsets_and_calibration() {
+        let playlist = parse_playlist(
+            r#"
+            [volume]
+            level = 80
+            schedule = [
+                { from = 09:00:00, to = 12:00:00, level = 60 },
+                { from = 22:00:00, to = 06:00:00, level = 30 },
+                { from = 12:00:00, to = 14:00:00, level = 190, weekdays = [6, 7] },
+            ]
+            [volume.offsets]
+            music = -5
+            advertizement = 5
+            announcement = 20
+            message = -50
+            "#,
+        );
+        let calibrated = parse_playlist(
+            r#"
+            [volume]
+            level = 80
+            schedule = [{ from = 09:00:00, to = 12:00:00, level = 60 }]
+            calibration = -10
+            [volume.offsets]
+            music = -5
+            "#,
+        );
+        // 2024-12-30 is monday, 2025-01-04 is saturday
+        let cases = [
+            (&playlist, "2024-12-30T08:59:59", ContentType::Music, 75),
+            (&playlist, "2024-12-30T09:00:00", ContentType::Music, 55),
+            (
+                &playlist,
+                "2024-12-30T09:00:00",
+                ContentType::Advertizement,
+                65,
+            ),
+            (&playlist, "2024-12-30T09:00:00", ContentType::Event, 60),
+            (
+                &playlist,
+                "2024-12-30T12:00:00",
+                ContentType::Announcement,
+                100,
+            ),
+            // interval passes midnight
+            (&playlist, "2024-12-30T23:30:00", ContentType::Music, 25),
+            (&playlist, "2024-12-31T05:59:59", ContentType::Music, 25),
+            (&playlist, "2024-12-31T06:00:00", ContentType::Music, 75),
+            // weekdays and clamping
+            (
+                &playlist,
+                "2024-12-30T13:00:00",
+                ContentType::Announcement,
+                100,
+            ),
+            (
+                &playlist,
+                "2025-01-04T13:00:00",
+                ContentType::Announcement,
+                200,
+            ),
+            (&playlist, "2024-12-30T23:30:00", ContentType::Message, 0),
+            (&calibrated, "2024-12-30T10:00:00", ContentType::Music, 45),
+        ];
+        for (playlist, dt, content_type, expected) in cases {
+            assert_eq!(
+                playlist.get_volume(datetime(dt), content_type),
+                expected,
+                "{} {:?}",
+                dt,
+                content_type
+            );
+        }
+        assert_eq!(
+            parse_playlist("[music]\nshuffle = true")
+                .get_volume(datetime("2024-12-30T10:00:00"), ContentType::Music),
+            DEFAULT_VOLUME_LEVEL
+        );
+    }
+
+    #[test]
+    fn next_schedule_datetime_is_the_nearest_change() {
+        let week = "[[09:00:00, 21:00:00], [09:00:00, 21:00:00], [09:00:00, 21:00:00], [09:00:00, 21:00:00], [09:00:00, 21:00:00], [09:00:00, 21:00:00], [09:00:00, 21:00:00]]";
+        let advertizement =
+            parse_playlist("[advertizement.schedule]\n'00:10:00' = ['a']\n'00:40:00' = ['b']");
+        let time_announcement = parse_playlist("[time_announcement]\nfolder = 'time'");
+        let events = parse_playlist(
+            "[events.daily]\ntime = 12:00:00\nfile = 'daily.mp3'\n[events.once]\nat = 2024-12-30T15:30:00\nfile = 'once.mp3'",
+        );
+        let working_hours = parse_playlist(&format!("[working_hours]\nschedule = {}", week));
+        let announcements = parse_playlist(&format!(
+            "[working_hours]\nschedule = {}\n[working_hours_announcement.closing]\n'00:15:00' = 'closing.mp3'",
+            week
+        ));
+        let cases = [
+            (&advertizement, "2024-12-30T10:05:00", "2024-12-30T10:10:00"),
+            (&advertizement, "2024-12-30T10:10:00", "2024-12-30T10:40:00"),
+            (&advertizement, "2024-12-30T10:45:00", "2024-12-30T11:10:00"),
+            (&advertizement, "2024-12-30T23:50:00", "2024-12-31T00:10:00"),
+            (
+                &time_announcement,
+                "2024-12-30T10:30:00",
+                "2024-12-30T11:00:00",
+            ),
+            (
+                &time_announcement,
+                "2024-12-30T23:30:00",
+                "2024-12-31T00:00:00",
+            ),
+            (&events, "2024-12-30T11:00:00", "2024-12-30T12:00:00"),
+            (&events, "2024-12-30T12:00:00", "2024-12-30T15:30:00"),
+            (&events, "2024-12-30T16:00:00", "2024-12-31T12:00:00"),
+            (&working_hours, "2024-12-30T08:00:00", "2024-12-30T09:00:00"),
+            // working time is over after its end
+            (&working_hours, "2024-12-30T10:00:00", "2024-12-30T21:00:01"),
+            (&working_hours, "2024-12-30T21:30:00", "2024-12-31T09:00:00"),
+            (&announcements, "2024-12-30T10:00:00", "2024-12-30T20:45:00"),
+        ];
+        for (playlist, dt, expected) in cases {
+            assert_eq!(
+                playlist.get_next_schedule_datetime(datetime(dt)),
+                Some(datetime(expected)),
+                "{}",
+                dt
+            );
+        }
+        let music = parse_playlist("[music]\nshuffle = true");
+        assert!(music
+            .get_next_schedule_datetime(datetime("2024-12-30T10:00:00"))
+            .is_none());
+    }
+
+    const DEFAULT: &str = r#"
+        [working_hours]
+        schedule = [[09:00:00, 21:00:00], [09:00:00, 21:00:00]]
+        [working_hours.exceptions]
+        2024-12-31 = [10:00:00, 18:00:00]
+        2025-01-01 = [12:00:00, 18:00:00]
+
+        [music]
+        shuffle = true
+        resume_interrupted = true
+        schedule = [[2024-01-01, 2024-12-31, ["music/common"]]]
+
+        [advertizement]
+        start_jingle = "jingles/start.mp3"
+        [advertizement.schedule]
+        "00:10:00" = ["ads/short"]
+        "00:40:00" = ["ads/long"]
+
+        [time_announcement]
+        folder = "time/female"
+
+        [working_hours_announcement]
+        opening = "announcements/opening.mp3"
+        [working_hours_announcement.closing]
+        "00:15:00" = "announcements/closing_15.mp3"
+
+        [events.sale]
+        time = 12:00:00
+        file = "events/sale.mp3"
+        priority = 80
+
+        [events.lunch]
+        time = 13:00:00
+        file = "events/lunch.mp3"
+
+        [fade]
+        duration = 3000
+        steps = 10
+
+        [ducking]
+        level = 30
+        time_announcement = true
+
+        [volume]
+        level = 80
+        schedule = [{ from = 09:00:00, to = 12:00:00, level = 60 }]
+        [volume.offsets]
+        music = -5
+        advertizement = 5
+    "#;
+
+    fn merged(documents: &[&str]) -> Playlist {
+        let origin = Path::new("playlist.toml");
+        let mut layers = documents.iter().map(|d| parse_layer(origin, d).unwrap());
+        let mut value = layers.next().unwrap();
+        for layer in layers {
+            merge::merge_values(&mut value, layer);
+        }
+        merge::strip_directives(&mut value);
+        to_playlist(&value).unwrap()
+    }
+
+    #[test]
+    fn working_hours_exceptions_are_merged_by_date() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[working_hours.exceptions]\n2025-03-08 = [11:00:00, 17:00:00]\n_remove = [2025-01-01]",
+        ]);
+        let working_hours = playlist.working_hours.unwrap();
+        assert_eq!(working_hours.schedule.unwrap().len(), 2);
+        let exceptions = working_hours.exceptions.unwrap();
+        let mut dates: Vec<&NaiveDate> = exceptions.keys().collect();
+        dates.sort();
+        assert_eq!(dates, [&date("2024-12-31"), &date("2025-03-08")]);
+        assert_eq!(exceptions[&date("2025-03-08")].0, time("11:00:00"));
+    }
+
+    #[test]
+    fn working_hours_exceptions_are_replaced() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[working_hours.exceptions]\n_replace = true\n2025-03-08 = [11:00:00, 17:00:00]",
+        ]);
+        let exceptions = playlist.working_hours.unwrap().exceptions.unwrap();
+        assert_eq!(exceptions.len(), 1);
+        assert!(exceptions.contains_key(&date("2025-03-08")));
+    }
+
+    #[test]
+    fn music_fields_are_merged_and_schedule_is_replaced() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[music]\nshuffle = false\nschedule = [[2024-06-01, 2024-08-31, ['music/summer']]]",
+        ]);
+        let music = playlist.music.unwrap();
+        assert_eq!(music.shuffle, Some(false));
+        assert_eq!(music.resume_interrupted, Some(true));
+        let schedule = music.schedule.unwrap();
+        assert_eq!(schedule.len(), 1);
+        assert_eq!(schedule[0].2, ["music/summer"]);
+    }
+
+    #[test]
+    fn advertizement_slots_are_merged_by_time() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[advertizement]\nend_jingle = 'jingles/end.mp3'\n[advertizement.schedule]\n'00:20:00' = ['ads/medium']\n_remove = ['00:40:00']",
+        ]);
+        let advertizement = playlist.advertizement.unwrap();
+        assert_eq!(
+            advertizement.start_jingle.as_deref(),
+            Some("jingles/start.mp3")
+        );
+        assert_eq!(advertizement.end_jingle.as_deref(), Some("jingles/end.mp3"));
+        let schedule = advertizement.schedule.unwrap();
+        let mut slots: Vec<&NaiveTime> = schedule.keys().collect();
+        slots.sort();
+        assert_eq!(slots, [&time("00:10:00"), &time("00:20:00")]);
+    }
+
+    #[test]
+    fn time_announcement_is_merged_by_fields_and_removed() {
+        let playlist = merged(&[DEFAULT, "[time_announcement]\nfolder = 'time/male'"]);
+        assert_eq!(
+            playlist.time_announcement.unwrap().folder.as_deref(),
+            Some("time/male")
+        );
+
+        let playlist = merged(&[DEFAULT, "_remove = ['time_announcement']"]);
+        assert!(playlist.time_announcement.is_none());
+        assert!(playlist.music.is_some());
+    }
+
+    #[test]
+    fn working_hours_announcement_closing_is_merged_by_time() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[working_hours_announcement.closing]\n'00:05:00' = 'announcements/closing_5.mp3'",
+        ]);
+        let announcement = playlist.working_hours_announcement.unwrap();
+        assert_eq!(
+            announcement.opening.as_deref(),
+            Some("announcements/opening.mp3")
+        );
+        assert_eq!(announcement.closing.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn events_are_merged_by_id_and_fields() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[events]\n_remove = ['lunch']\n[events.sale]\ntime = 15:00:00\n[events.close]\n_replace = true\ntime = 20:50:00\ntext = 'Closing soon'",
+        ]);
+        let events = playlist.events.unwrap();
+        let mut ids: Vec<&String> = events.keys().collect();
+        ids.sort();
+        assert_eq!(ids, ["close", "sale"]);
+        let sale = &events["sale"];
+        assert_eq!(sale.time, Some(time("15:00:00")));
+        assert_eq!(sale.file.as_deref(), Some("events/sale.mp3"));
+        assert_eq!(sale.priority, Some(80));
+    }
+
+    #[test]
+    fn replaced_event_does_not_inherit_fields() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[events.sale]\n_replace = true\ntime = 16:00:00\ntext = 'Sale'",
+        ]);
+        let sale = &playlist.events.unwrap()["sale"];
+        assert_eq!(sale.text.as_deref(), Some("Sale"));
+        assert!(sale.file.is_none());
+        assert!(sale.priority.is_none());
+    }
+
+    #[test]
+    fn fade_and_ducking_are_merged_by_fields() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[fade]\ncurve = 'log'\n[ducking]\nlevel = 20\n_remove = ['time_announcement']",
+        ]);
+        let fade = playlist.fade.unwrap();
+        assert_eq!(fade.duration, Some(3000));
+        assert_eq!(fade.curve, Some(FadeCurve::Log));
+        let ducking = playlist.ducking.unwrap();
+        assert_eq!(ducking.level, Some(20));
+        assert!(ducking.time_announcement.is_none());
+    }
+
+    #[test]
+    fn volume_offsets_are_merged_and_schedule_is_replaced() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[volume]\nschedule = []\n[volume.offsets]\nmusic = -10\nevent = 3",
+        ]);
+        let volume = playlist.volume.unwrap();
+        assert_eq!(volume.level, Some(80));
+        assert!(volume.schedule.unwrap().is_empty());
+        let offsets = volume.offsets.unwrap();
+        assert_eq!(offsets.music, Some(-10));
+        assert_eq!(offsets.advertizement, Some(5));
+        assert_eq!(offsets.event, Some(3));
+    }
+
+    #[test]
+    fn layers_are_merged_in_order() {
+        let playlist = merged(&[
+            DEFAULT,
+            "[volume]\nlevel = 70",
+            "[volume]\nlevel = 90\n[fade]\n_replace = true\nsteps = 5",
+        ]);
+        assert_eq!(playlist.volume.unwrap().level, Some(90));
+        let fade = playlist.fade.unwrap();
+        assert_eq!(fade.steps, Some(5));
+        assert!(fade.duration.is_none());
+    }
+
+    #[test]
+    fn layers_of_different_formats_are_merged() {
+        let folder = tempfile::TempDir::new().unwrap();
+        let documents = [
+            ("playlist.toml", DEFAULT),
+            ("playlist_group_north.yaml", "volume:\n  level: 70\n  schedule: [{ from: '09:00:00', to: '12:00:00', level: 60 }]"),
+            (
+                "playlist_tag_24h.json",
+                r#"{"working_hours": {"exceptions": {"2025-03-08": ["10:00:00", "16:00:00"], "_remove": ["2025-01-01"]}}}"#,
+            ),
+            // TOML document is used when there are several
+            ("playlist_north1.toml", "[volume]\nlevel = 90"),
+            ("playlist_north1.json", r#"{"volume": {"level": 50}}"#),
+        ];
+        for (name, content) in documents {
+            fs::write(folder.path().join(name), content).unwrap();
+        }
+        let node = Node {
+            name: Some("north1".to_string()),
+            groups: Some(vec!["north".to_string()]),
+            tags: Some(vec!["24h".to_string()]),
+        };
+        let source = PlaylistSource::Folder(folder.path().to_path_buf());
+
+        let (playlist, _) = Playlist::read_version(&source, &node, None).unwrap();
+        let volume = playlist.volume.unwrap();
+        assert_eq!(volume.level, Some(90));
+        assert_eq!(volume.schedule.unwrap()[0].from, time("09:00:00"));
+        let exceptions = playlist.working_hours.unwrap().exceptions.unwrap();
+        let mut dates: Vec<&NaiveDate> = exceptions.keys().collect();
+        dates.sort();
+        assert_eq!(dates, [&date("2024-12-31"), &date("2025-03-08")]);
+        assert_eq!(exceptions[&date("2025-03-08")].1, time("16:00:00"));
+    }
+
+    #[test]
+    fn working_day_lasts_until_next_opening() {
+        let playlist = merged(&[DEFAULT]);
+        // working hours are defined for monday and tuesday only
+        let cases = [
+            ("2025-03-03T08:59:00", Some("2025-02-25T09:00:00")),
+            ("2025-03-03T09:00:00", Some("2025-03-03T09:00:00")),
+            ("2025-03-03T23:30:00", Some("2025-03-03T09:00:00")),
+            ("2025-03-04T01:00:00", Some("2025-03-03T09:00:00")),
+            ("2025-03-08T12:00:00", Some("2025-03-04T09:00:00")),
+        ];
+        for (dt, expected) in cases {
+            assert_eq!(
+                playlist.get_working_day_start(datetime(dt)),
+                expected.map(datetime),
+                "{}",
+                dt
+            );
+        }
+        let always_open = merged(&["[music]\nshuffle = true"]);
+        assert_eq!(
+            always_open.get_working_day_start(datetime("2025-03-03T12:00:00")),
+            None
+        );
+    }
+
+    #[test]
+    fn layers_are_ordered_from_default_to_node() {
+        let node = Node {
+            name: Some("pc101".to_string()),
+            groups: Some(vec!["north".to_string(), "mall".to_string()]),
+            tags: Some(vec!["24h".to_string()]),
+        };
+        assert_eq!(
+            get_layer_names(&node, None),
+            [
+                "playlist",
+                "playlist_group_north",
+                "playlist_group_mall",
+                "playlist_tag_24h",
+                "playlist_pc101"
+            ]
+        );
+        assert_eq!(
+            get_layer_names(&node, Some("2"))[1],
+            "versions/2/playlist_group_north"
+        );
+    }
+
+    #[test]
+    fn explain_reports_winning_layer_of_each_setting() {
+        let media = tempfile::TempDir::new().unwrap();
+        let cfg = media.path().join("cfg");
+        fs::create_dir_all(&cfg).unwrap();
+        let documents = [
+            (
+                "playlist.toml",
+                "[volume]\nlevel = 50\ncalibration = 1\nramp_step = 2\n[fade]\nsteps = 5",
+            ),
+            (
+                "playlist_group_north.toml",
+                "[volume]\nlevel = 60\ncalibration = 2",
+            ),
+            ("playlist_group_mall.toml", "[volume]\nlevel = 70"),
+            (
+                "playlist_tag_24h.toml",
+                "[volume]\nlevel = 80\n[fade]\n_replace = true\nduration = 100",
+            ),
+            ("playlist_pc101.toml", "[volume]\nlevel = 90"),
+        ];
+        for (name, content) in documents {
+            fs::write(cfg.join(name), content).unwrap();
+        }
+        let config = format!(
+            "[media]\nfolder = {:?}\n[node]\nname = \"pc101\"\ngroups = [\"north\", \"mall\"]\ntags = [\"24h\"]",
+            media.path().to_string_lossy()
+        );
+        let node_config: NodeConfig = toml::from_str(&config).unwrap();
+
+        let settings: Vec<(String, String, PathBuf)> = Playlist::explain(&node_config)
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.key, s.value.to_string(), s.origin))
+            .collect();
+        let expected = [
+            ("fade.duration", "100", "playlist_tag_24h.toml"),
+            ("volume.calibration", "2", "playlist_group_north.toml"),
+            ("volume.level", "90", "playlist_pc101.toml"),
+            ("volume.ramp_step", "2", "playlist.toml"),
+        ];
+        let expected: Vec<(String, String, PathBuf)> = expected
+            .iter()
+            .map(|(k, v, o)| (k.to_string(), v.to_string(), cfg.join(o)))
+            .collect();
+        assert_eq!(settings, expected);
+    }
 }
@@ -0,0 +1,422 @@
+use crate::config::NodeConfig;
+use crate::error::{Error, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Remote documents poll interval if not defined in node settings, in seconds
+const DEFAULT_POLL_INTERVAL: u64 = 60;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Location of playlist documents: `cfg` folder inside media folder or HTTP(S) endpoint
+pub enum PlaylistSource {
+    Folder(PathBuf),
+    Remote(RemoteSource),
+}
+
+/// HTTP(S) endpoint polled with conditional requests, the last good copy of each document is
+/// cached inside media folder and used while endpoint is unreachable
+pub struct RemoteSource {
+    url: String,
+    cache_folder: PathBuf,
+    interval: Duration,
+    agent: ureq::Agent,
+}
+
+/// Cached document validators and the last check time
+#[derive(Serialize, Deserialize, Default)]
+struct CacheMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    checked: u64,
+    missing: bool,
+}
+
+impl PlaylistSource {
+    pub fn from_config(node_config: &NodeConfig) -> PlaylistSource {
+        let media_folder = Path::new(&node_config.media.folder);
+        match node_config.playlist.as_ref() {
+            Some(remote) => PlaylistSource::Remote(RemoteSource {
+                url: remote.url.trim_end_matches('/').to_string(),
+                cache_folder: media_folder.join("state").join("playlist_cache"),
+                interval: Duration::from_secs(remote.interval.unwrap_or(DEFAULT_POLL_INTERVAL)),
+                agent: ureq::AgentBuilder::new()
+                    .timeout_connect(CONNECT_TIMEOUT)
+                    .timeout_read(READ_TIMEOUT)
+                    .build(),
+            }),
+            None => PlaylistSource::Folder(media_folder.join("cfg")),
+        }
+    }
+
+    /// Returns file path or URL of document, used in error messages
+    pub fn get_origin(&self, name: &str) -> PathBuf {
+        match self {
+            PlaylistSource::Folder(folder) => folder.join(name),
+            PlaylistSource::Remote(remote) => PathBuf::from(format!("{}/{}", remote.url, name)),
+        }
+    }
+
+    /// Returns parsed document, None if it does not exist
+    pub fn read<T>(
+        &self,
+        name: &str,
+        parse: impl Fn(&Path, &str) -> Result<T>,
+    ) -> Result<Option<T>> {
+        match self {
+            PlaylistSource::Folder(folder) => {
+                let path = folder.join(name);
+                match fs::read_to_string(&path) {
+                    Ok(content) => parse(&path, &content).map(Some),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                    Err(source) => Err(Error::PlaylistRead { path, source }),
+                }
+            }
+            PlaylistSource::Remote(remote) => remote.read(name, parse),
+        }
+    }
+}
+
+impl RemoteSource {
+    fn read<T>(&self, name: &str, parse: impl Fn(&Path, &str) -> Result<T>) -> Result<Option<T>> {
+        let url = format!("{}/{}", self.url, name);
+        let cache_path = self.cache_folder.join(name);
+        let meta_path = self.cache_folder.join(format!("{}.meta", name));
+        let mut meta: CacheMeta = fs::read_to_string(&meta_path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        let cached = fs::read_to_string(&cache_path).ok();
+        let read_cache = |meta: &CacheMeta| -> Result<Option<T>> {
+            match (&cached, meta.missing) {
+                (_, true) => Ok(None),
+                (Some(content), false) => parse(&cache_path, content).map(Some),
+                (None, false) => Err(Error::PlaylistFetch {
+                    url: url.clone(),
+                    reason: "no cached copy".to_string(),
+                }),
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let fresh = now.saturating_sub(meta.checked) < self.interval.as_secs();
+        if fresh && (cached.is_some() || meta.missing) {
+            return read_cache(&meta);
+        }
+        meta.checked = now;
+
+        let mut request = self.agent.get(&url);
+        if cached.is_some() {
+            if let Some(etag) = meta.etag.as_ref() {
+                request = request.set("If-None-Match", etag);
+            }
+            if let Some(last_modified) = meta.last_modified.as_ref() {
+                request = request.set("If-Modified-Since", last_modified);
+            }
+        }
+        let result = match request.call() {
+            Ok(response) if response.status() == 304 => read_cache(&meta),
+            Ok(response) => {
+                let etag = response.header("ETag").map(str::to_string);
+                let last_modified = response.header("Last-Modified").map(str::to_string);
+                match response.into_string() {
+                    Err(e) => {
+                        log::warn!("cannot fetch {}, cached copy is used: {}", url, e);
+                        read_cache(&meta)
+                    }
+                    Ok(content) => match parse(Path::new(&url), &content) {
+                        Ok(document) => {
+                            meta = CacheMeta {
+                                etag,
+                                last_modified,
+                                checked: now,
+                                missing: false,
+                            };
+                            self.write_cache(&cache_path, &content);
+                            log::info!("playlist document {} updated", url);
+                            Ok(Some(document))
+                        }
+                        // the last good copy is kept
+                        Err(e) if cached.is_some() && !meta.missing => {
+                            e.log();
+                            read_cache(&meta)
+                        }
+                        Err(e) => Err(e),
+                    },
+                }
+            }
+            Err(ureq::Error::Status(404, _)) => {
+                let _ = fs::remove_file(&cache_path);
+                meta = CacheMeta {
+                    checked: now,
+                    missing: true,
+                    ..Default::default()
+                };
+                Ok(None)
+            }
+            Err(e) => {
+                log::warn!("cannot fetch {}, cached copy is used: {}", url, e);
+                read_cache(&meta)
+            }
+        };
+
+        match serde_json::to_string(&meta) {
+            Ok(content) => self.write_cache(&meta_path, &content),
+            Err(e) => log::error!("cannot serialize playlist cache state: {}", e),
+        }
+        result
+    }
+
+    /// Replaces cached file at once
+    fn write_cache(&self, path: &Path, content: &str) {
+        // versioned documents are cached in subfolders
+        let _ = fs::create_dir_all(path.parent().unwrap_or(&self.cache_folder));
+        let tmp_path = path.with_extension("tmp");
+        if let Err(e) = fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, path)) {
+            log::error!("cannot write playlist cache {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use tempfile::TempDir;
+
+    /// Requested path, `If-None-Match` header and response status
+    type Request = (String, Option<String>, u16);
+
+    /// Playlist server answering conditional requests, records each request
+    struct PlaylistServer {
+        url: String,
+        documents: Arc<Mutex<HashMap<String, String>>>,
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl PlaylistServer {
+        fn start() -> PlaylistServer {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let documents: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let (served, recorded) = (documents.clone(), requests.clone());
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let mut request_line = String::new();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    reader.read_line(&mut request_line).unwrap();
+                    let mut if_none_match = None;
+                    let mut header = String::new();
+                    while reader.read_line(&mut header).unwrap() > 2 {
+                        if let Some((name, value)) = header.split_once(':') {
+                            if name.eq_ignore_ascii_case("If-None-Match") {
+                                if_none_match = Some(value.trim().to_string());
+                            }
+                        }
+                        header.clear();
+                    }
+                    let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+                    let document = served.lock().unwrap().get(&path).cloned();
+                    let (status, head, body) = match document {
+                        Some(content) => {
+                            let etag = get_etag(&content);
+                            match if_none_match.as_ref() == Some(&etag) {
+                                true => (304, "304 Not Modified".to_string(), String::new()),
+                                false => (200, format!("200 OK\r\nETag: {}", etag), content),
+                            }
+                        }
+                        None => (404, "404 Not Found".to_string(), String::new()),
+                    };
+                    recorded.lock().unwrap().push((path, if_none_match, status));
+                    write!(
+                        stream,
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        head,
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            });
+            PlaylistServer {
+                url,
+                documents,
+                requests,
+            }
+        }
+
+        fn set_document(&self, name: &str, content: &str) {
+            self.documents
+                .lock()
+                .unwrap()
+                .insert(format!("/{}", name), content.to_string());
+        }
+
+        fn take_requests(&self) -> Vec<Request> {
+            std::mem::take(&mut *self.requests.lock().unwrap())
+        }
+    }
+
+    fn get_etag(content: &str) -> String {
+        format!(
+            "\"{}-{}\"",
+            content.len(),
+            content.bytes().map(u64::from).sum::<u64>()
+        )
+    }
+
+    /// Returns source checking documents on each read, documents are cached inside given media folder
+    fn remote_source(url: &str, media: &TempDir) -> PlaylistSource {
+        let config = format!(
+            "[media]\nfolder = {:?}\n[node]\nname = \"pc101\"\n[playlist]\nurl = {:?}\ninterval = 0",
+            media.path().to_string_lossy(),
+            url
+        );
+        PlaylistSource::from_config(&toml::from_str(&config).unwrap())
+    }
+
+    fn parse(path: &Path, content: &str) -> Result<toml::Value> {
+        toml::from_str(content).map_err(|source| Error::PlaylistParse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn read(source: &PlaylistSource, name: &str) -> Result<Option<toml::Value>> {
+        source.read(name, parse)
+    }
+
+    fn document(content: &str) -> Option<toml::Value> {
+        Some(toml::from_str(content).unwrap())
+    }
+
+    #[test]
+    fn unchanged_document_is_read_from_cache() {
+        let server = PlaylistServer::start();
+        let media = TempDir::new().unwrap();
+        let source = remote_source(&server.url, &media);
+        server.set_document("playlist.toml", "level = 1");
+
+        assert_eq!(
+            read(&source, "playlist.toml").unwrap(),
+            document("level = 1")
+        );
+        assert_eq!(
+            read(&source, "playlist.toml").unwrap(),
+            document("level = 1")
+        );
+        let etag = get_etag("level = 1");
+        assert_eq!(
+            server.take_requests(),
+            [
+                ("/playlist.toml".to_string(), None, 200),
+                ("/playlist.toml".to_string(), Some(etag.clone()), 304),
+            ]
+        );
+
+        server.set_document("playlist.toml", "level = 22");
+        assert_eq!(
+            read(&source, "playlist.toml").unwrap(),
+            document("level = 22")
+        );
+        assert_eq!(
+            server.take_requests(),
+            [("/playlist.toml".to_string(), Some(etag), 200)]
+        );
+    }
+
+    #[test]
+    fn missing_document_is_skipped() {
+        let server = PlaylistServer::start();
+        let media = TempDir::new().unwrap();
+        let source = remote_source(&server.url, &media);
+
+        assert_eq!(read(&source, "playlist_pc101.toml").unwrap(), None);
+        assert_eq!(server.take_requests()[0].2, 404);
+
+        // document removed from server is not read from cache
+        server.set_document("playlist_pc101.toml", "level = 1");
+        assert!(read(&source, "playlist_pc101.toml").unwrap().is_some());
+        server.documents.lock().unwrap().clear();
+        assert_eq!(read(&source, "playlist_pc101.toml").unwrap(), None);
+    }
+
+    #[test]
+    fn cached_copy_is_used_while_server_is_unreachable_or_document_is_invalid() {
+        let server = PlaylistServer::start();
+        let media = TempDir::new().unwrap();
+        let source = remote_source(&server.url, &media);
+        server.set_document("playlist.toml", "level = 1");
+        read(&source, "playlist.toml").unwrap();
+        read(&source, "playlist_pc101.toml").unwrap();
+
+        server.set_document("playlist.toml", "level = ");
+        assert_eq!(
+            read(&source, "playlist.toml").unwrap(),
+            document("level = 1")
+        );
+
+        let closed_url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let unreachable = remote_source(&closed_url, &media);
+        assert_eq!(
+            read(&unreachable, "playlist.toml").unwrap(),
+            document("level = 1")
+        );
+        assert_eq!(read(&unreachable, "playlist_pc101.toml").unwrap(), None);
+        assert!(matches!(
+            read(&unreachable, "playlist_north.toml"),
+            Err(Error::PlaylistFetch { .. })
+        ));
+    }
+
+    #[test]
+    fn cached_copy_is_used_when_response_is_cut_off() {
+        let server = PlaylistServer::start();
+        let media = TempDir::new().unwrap();
+        server.set_document("playlist.toml", "level = 1");
+        read(&remote_source(&server.url, &media), "playlist.toml").unwrap();
+
+        // body is shorter than declared length
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut request_line).unwrap() > 2 {
+                    request_line.clear();
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\nlevel = 2"
+                );
+            }
+        });
+        let cut_off = remote_source(&url, &media);
+        assert_eq!(
+            read(&cut_off, "playlist.toml").unwrap(),
+            document("level = 1")
+        );
+        assert!(matches!(
+            read(&cut_off, "playlist_north.toml"),
+            Err(Error::PlaylistFetch { .. })
+        ));
+        let cache_folder = media.path().join("state").join("playlist_cache");
+        assert!(cache_folder.join("playlist_north.toml.meta").exists());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::config::NodeConfig;
+use chrono::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuarantineEntry {
+    pub path: String,
+    pub reason: String,
+    pub mtime: u64,
+    pub since: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct QuarantineFile {
+    files: Vec<QuarantineEntry>,
+}
+
+/// Per-node list of media files which failed to play, file stays in quarantine until its mtime changes
+pub struct Quarantine {
+    file_path: PathBuf,
+    entries: HashMap<PathBuf, QuarantineEntry>,
+}
+
+impl Quarantine {
+    pub fn load(node_config: &NodeConfig) -> Quarantine {
+        let media_folder = &node_config.media.folder;
+        let node_name = node_config.node.name.clone().unwrap_or_default();
+        let file_path = Path::new(media_folder)
+            .join("state")
+            .join(format!("quarantine_{}.toml", node_name));
+
+        let mut entries = HashMap::new();
+        if let Ok(content) = fs::read_to_string(&file_path) {
+            match toml::from_str::<QuarantineFile>(&content) {
+                Ok(q) => {
+                    for entry in q.files {
+                        entries.insert(PathBuf::from(&entry.path), entry);
+                    }
+                }
+                Err(e) => log::error!("cannot parse quarantine file {:?}: {}", file_path, e),
+            }
+        }
+
+        let mut quarantine = Quarantine { file_path, entries };
+        quarantine.release_changed();
+        for entry in quarantine.entries.values() {
+            log::warn!("{:?} is in quarantine: {}", entry.path, entry.reason);
+        }
+        quarantine
+    }
+
+    /// Returns true if file is in quarantine and was not changed since
+    pub fn is_quarantined(&self, path: &Path) -> bool {
+        match self.entries.get(path) {
+            Some(entry) => get_mtime(path) == Some(entry.mtime),
+            None => false,
+        }
+    }
+
+    pub fn add(&mut self, path: &Path, reason: &str) {
+        log::warn!("{:?} moved to quarantine: {}", path, reason);
+        self.entries.insert(
+            path.to_path_buf(),
+            QuarantineEntry {
+                path: path.to_string_lossy().to_string(),
+                reason: reason.to_string(),
+                mtime: get_mtime(path).unwrap_or_default(),
+                since: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            },
+        );
+        self.save();
+    }
+
+    /// Removes changed and deleted files from quarantine
+    pub fn release_changed(&mut self) {
+        let changed: Vec<PathBuf> = self
+            .entries
+            .keys()
+            .filter(|p| !self.is_quarantined(p))
+            .cloned()
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+        for path in changed {
+            log::info!("{:?} was changed and released from quarantine", path);
+            self.entries.remove(&path);
+        }
+        self.save();
+    }
+
+    /// Returns quarantined files sorted by path
+    pub fn report(&self) -> Vec<QuarantineEntry> {
+        let mut entries: Vec<QuarantineEntry> = self.entries.values().cloned().collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+
+    fn save(&self) {
+        if let Some(parent) = self.file_path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let content = QuarantineFile {
+            files: self.report(),
+        };
+        match toml::to_string(&content) {
+            Ok(c) => {
+                if let Err(e) = fs::write(&self.file_path, c) {
+                    log::error!("cannot write quarantine file {:?}: {}", self.file_path, e);
+                }
+            }
+            Err(e) => log::error!("cannot serialize quarantine: {}", e),
+        }
+    }
+}
+
+fn get_mtime(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    fn node_config(media: &TempDir) -> NodeConfig {
+        let config = format!(
+            "[media]\nfolder = {:?}\n[node]\nname = \"pc101\"",
+            media.path().to_string_lossy()
+        );
+        toml::from_str(&config).unwrap()
+    }
+
+    fn set_mtime(path: &Path, mtime: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    #[test]
+    fn file_stays_in_quarantine_until_it_is_changed() {
+        let media = TempDir::new().unwrap();
+        let node_config = node_config(&media);
+        let track = media.path().join("broken.mp3");
+        fs::write(&track, "broken").unwrap();
+        let mtime = SystemTime::now() - Duration::from_secs(60);
+        set_mtime(&track, mtime);
+
+        let mut quarantine = Quarantine::load(&node_config);
+        assert!(!quarantine.is_quarantined(&track));
+        quarantine.add(&track, "decoder error");
+        assert!(quarantine.is_quarantined(&track));
+
+        // quarantine survives restart while file is not changed
+        let mut quarantine = Quarantine::load(&node_config);
+        quarantine.release_changed();
+        assert!(quarantine.is_quarantined(&track));
+        assert_eq!(quarantine.report()[0].reason, "decoder error");
+
+        fs::write(&track, "fixed").unwrap();
+        set_mtime(&track, mtime + Duration::from_secs(30));
+        assert!(!quarantine.is_quarantined(&track));
+        quarantine.release_changed();
+        assert!(quarantine.report().is_empty());
+        assert!(Quarantine::load(&node_config).report().is_empty());
+    }
+
+    #[test]
+    fn deleted_file_is_released() {
+        let media = TempDir::new().unwrap();
+        let node_config = node_config(&media);
+        let track = media.path().join("removed.mp3");
+        fs::write(&track, "broken").unwrap();
+
+        let mut quarantine = Quarantine::load(&node_config);
+        quarantine.add(&track, "playback is stuck");
+        fs::remove_file(&track).unwrap();
+        quarantine.release_changed();
+        assert!(quarantine.report().is_empty());
+    }
+}
@@ -0,0 +1,411 @@
+use crate::config::{Node, NodeConfig};
+use crate::error::{Error, Result};
+use crate::playlist::Playlist;
+use crate::playlist_source::PlaylistSource;
+use chrono::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rollout document name, it is placed next to playlist documents
+pub const ROLLOUT_DOCUMENT: &str = "rollout.toml";
+
+/// Staged rollout of playlist version: canary nodes apply version at once, nodes of listed
+/// groups and tags from `groups_from`, everyone from `everyone_from`, the rest use previous version
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Rollout {
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canary: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+    #[serde(
+        with = "toml_datetime_compat",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub groups_from: Option<NaiveDateTime>,
+    #[serde(
+        with = "toml_datetime_compat",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub everyone_from: Option<NaiveDateTime>,
+}
+
+impl Rollout {
+    /// Returns rollout from playlist source, None if there is no rollout document
+    pub fn read(source: &PlaylistSource) -> Result<Option<Rollout>> {
+        source.read(ROLLOUT_DOCUMENT, parse_rollout)
+    }
+
+    /// Replaces rollout document at once, so nodes never see half-written file
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        let write_error = |e: String| Error::Rollout(format!("cannot write {:?}: {}", path, e));
+        let content = toml::to_string(self).map_err(|e| write_error(e.to_string()))?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .map_err(|e| write_error(e.to_string()))
+    }
+
+    /// Returns true if version is rolled out to node, rollout without stages covers every node
+    pub fn is_rolled_out_to(&self, node: &Node, dt: NaiveDateTime) -> bool {
+        if self.canary.is_none() && self.groups.is_none() && self.everyone_from.is_none() {
+            return true;
+        }
+        let canary = node
+            .name
+            .as_ref()
+            .is_some_and(|name| self.canary.iter().flatten().any(|c| c == name));
+        let group = self.groups_from.is_none_or(|from| dt >= from)
+            && self.groups.iter().flatten().any(|g| node.is_member_of(g));
+        let everyone = self.everyone_from.is_some_and(|from| dt >= from);
+        canary || group || everyone
+    }
+
+    /// Returns version node should use, None for documents outside of `versions` folder
+    pub fn get_version_for(&self, node: &Node, dt: NaiveDateTime) -> Option<String> {
+        match self.is_rolled_out_to(node, dt) {
+            true => Some(self.version.clone()),
+            false => self.previous.clone(),
+        }
+    }
+}
+
+fn parse_rollout(origin: &Path, content: &str) -> Result<Rollout> {
+    let rollout: Rollout = toml::from_str(content).map_err(|source| Error::PlaylistParse {
+        path: origin.to_path_buf(),
+        source,
+    })?;
+    for version in std::iter::once(&rollout.version).chain(rollout.previous.iter()) {
+        if !is_valid_version(version) {
+            return Err(Error::Rollout(format!(
+                "invalid version {:?} in {:?}",
+                version, origin
+            )));
+        }
+    }
+    Ok(rollout)
+}
+
+/// Version is used as folder name, so it is limited to letters, digits, `.`, `-` and `_`
+fn is_valid_version(version: &str) -> bool {
+    !version.is_empty()
+        && !version.starts_with('.')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+/// Returns playlist version node should use now, None for documents outside of `versions` folder
+pub fn get_target_version(source: &PlaylistSource, node: &Node) -> Result<Option<String>> {
+    Ok(Rollout::read(source)?.and_then(|r| r.get_version_for(node, Local::now().naive_local())))
+}
+
+/// Returns version name for messages
+pub fn describe_version(version: Option<&str>) -> String {
+    match version {
+        Some(version) => format!("version {}", version),
+        None => "unversioned playlist".to_string(),
+    }
+}
+
+/// Result of the last playlist read, reported in node status and heartbeats
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RolloutStatus {
+    /// Version node should use
+    pub target: Option<String>,
+    /// Version node plays, differs from target after rollback
+    pub active: Option<String>,
+    /// Target version was applied successfully
+    pub applied: bool,
+    /// Target version failed, the last applied version is used
+    pub rolled_back: bool,
+    pub error: Option<String>,
+}
+
+/// Playlist version which node applied successfully the last time
+struct AppliedPlaylist {
+    version: Option<String>,
+    document: toml::Value,
+}
+
+/// Reads playlist version chosen by rollout, node rolls back to the last applied version
+/// when target version cannot be read or fails validation
+pub struct PlaylistLoader {
+    path: PathBuf,
+    applied: Option<AppliedPlaylist>,
+}
+
+impl PlaylistLoader {
+    pub fn new(node_config: &NodeConfig) -> PlaylistLoader {
+        let node_name = node_config.node.name.clone().unwrap_or_default();
+        let path = Path::new(&node_config.media.folder)
+            .join("state")
+            .join(format!("playlist_applied_{}.toml", node_name));
+        let applied = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| toml::from_str::<toml::Value>(&content).ok())
+            .and_then(|mut value| {
+                let table = value.as_table_mut()?;
+                Some(AppliedPlaylist {
+                    version: table
+                        .remove("version")
+                        .and_then(|v| v.as_str().map(str::to_string)),
+                    document: table.remove("playlist")?,
+                })
+            });
+        PlaylistLoader { path, applied }
+    }
+
+    /// Returns playlist with rollout status, error is returned only if there is no version to roll back to
+    pub fn load(&mut self, node_config: &NodeConfig) -> (Result<Playlist>, RolloutStatus) {
+        let source = PlaylistSource::from_config(node_config);
+        let node = &node_config.node;
+        let target = match get_target_version(&source, node) {
+            Ok(version) => version,
+            Err(e) => {
+                let reason = e.to_string();
+                return self.roll_back(None, e, reason);
+            }
+        };
+
+        let (playlist, document) = match Playlist::read_version(&source, node, target.as_deref()) {
+            Ok(read) => read,
+            Err(e) => {
+                let reason = e.to_string();
+                return self.roll_back(target, e, reason);
+            }
+        };
+        let problems = playlist.validate();
+        if !problems.is_empty() {
+            let e = Error::PlaylistInvalid(problems.len());
+            let reason = format!("{}: {}", e, problems.join("; "));
+            return self.roll_back(target, e, reason);
+        }
+
+        self.save(target.clone(), document);
+        let status = RolloutStatus {
+            target: target.clone(),
+            active: target,
+            applied: true,
+            rolled_back: false,
+            error: None,
+        };
+        (Ok(playlist), status)
+    }
+
+    fn roll_back(
+        &self,
+        target: Option<String>,
+        error: Error,
+        reason: String,
+    ) -> (Result<Playlist>, RolloutStatus) {
+        let mut status = RolloutStatus {
+            target,
+            active: None,
+            applied: false,
+            rolled_back: false,
+            error: Some(reason),
+        };
+        let Some(applied) = self.applied.as_ref() else {
+            return (Err(error), status);
+        };
+        match Playlist::from_document(&applied.document, &self.path) {
+            Ok(playlist) => {
+                status.active = applied.version.clone();
+                status.rolled_back = true;
+                (Ok(playlist), status)
+            }
+            Err(_) => (Err(error), status),
+        }
+    }
+
+    /// Keeps applied version in state folder, file is written only when version or document changes
+    fn save(&mut self, version: Option<String>, document: toml::Value) {
+        if self
+            .applied
+            .as_ref()
+            .is_some_and(|a| a.version == version && a.document == document)
+        {
+            return;
+        }
+        let mut table = toml::value::Table::new();
+        if let Some(version) = version.as_ref() {
+            table.insert("version".to_string(), toml::Value::String(version.clone()));
+        }
+        table.insert("playlist".to_string(), document.clone());
+        let result = toml::to_string(&toml::Value::Table(table))
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                }
+                let tmp_path = self.path.with_extension("tmp");
+                fs::write(&tmp_path, content)
+                    .and_then(|_| fs::rename(&tmp_path, &self.path))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            log::error!("cannot save applied playlist {:?}: {}", self.path, e);
+        }
+        self.applied = Some(AppliedPlaylist { version, document });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{datetime, node};
+    use tempfile::TempDir;
+
+    #[test]
+    fn version_is_rolled_out_in_stages() {
+        let rollout: Rollout = toml::from_str(
+            r#"
+            version = "2"
+            previous = "1"
+            canary = ["pc101"]
+            groups = ["north"]
+            groups_from = 2025-03-01T12:00:00
+            everyone_from = 2025-03-02T09:00:00
+            "#,
+        )
+        .unwrap();
+        let canary = node("pc101", &[]);
+        let north = node("pc102", &["north"]);
+        let south = node("pc103", &["south"]);
+
+        let before = datetime("2025-03-01T10:00:00");
+        assert_eq!(
+            rollout.get_version_for(&canary, before).as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            rollout.get_version_for(&north, before).as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            rollout.get_version_for(&south, before).as_deref(),
+            Some("1")
+        );
+
+        let groups_stage = datetime("2025-03-01T12:00:00");
+        assert_eq!(
+            rollout.get_version_for(&north, groups_stage).as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            rollout.get_version_for(&south, groups_stage).as_deref(),
+            Some("1")
+        );
+
+        let everyone_stage = datetime("2025-03-02T09:00:00");
+        assert_eq!(
+            rollout.get_version_for(&south, everyone_stage).as_deref(),
+            Some("2")
+        );
+    }
+
+    #[test]
+    fn rollout_without_stages_covers_every_node() {
+        let rollout: Rollout = toml::from_str("version = \"2\"").unwrap();
+        let dt = datetime("2025-03-01T10:00:00");
+        assert_eq!(
+            rollout.get_version_for(&node("pc101", &[]), dt).as_deref(),
+            Some("2")
+        );
+    }
+
+    #[test]
+    fn nodes_outside_of_stage_use_unversioned_documents_without_previous_version() {
+        let rollout: Rollout = toml::from_str("version = \"2\"\ncanary = [\"pc101\"]").unwrap();
+        let dt = datetime("2025-03-01T10:00:00");
+        assert_eq!(rollout.get_version_for(&node("pc102", &[]), dt), None);
+    }
+
+    #[test]
+    fn version_must_be_folder_name() {
+        for version in ["../cfg", "", ".hidden", "a/b"] {
+            let content = format!("version = {:?}", version);
+            assert!(parse_rollout(Path::new(ROLLOUT_DOCUMENT), &content).is_err());
+        }
+        assert!(parse_rollout(Path::new(ROLLOUT_DOCUMENT), "version = \"2025-03-01.1\"").is_ok());
+    }
+
+    const VALID_PLAYLIST: &str = "[music]\nschedule = [[2025-01-01, 2025-12-31, ['music']]]";
+
+    /// Returns config of node which media folder contains versions 1 and 2 of playlist,
+    /// version 2 fails validation
+    fn node_config(media: &TempDir) -> NodeConfig {
+        let cfg = media.path().join("cfg");
+        for (version, content) in [
+            ("1", VALID_PLAYLIST),
+            ("2", "[volume]\nlevel = 500"),
+            ("3", "[music]\nshuffle = false"),
+        ] {
+            let folder = cfg.join("versions").join(version);
+            fs::create_dir_all(&folder).unwrap();
+            fs::write(folder.join("playlist.toml"), content).unwrap();
+        }
+        let config = format!(
+            "[media]\nfolder = {:?}\n[node]\nname = \"pc101\"",
+            media.path().to_string_lossy()
+        );
+        toml::from_str(&config).unwrap()
+    }
+
+    fn roll_out(media: &TempDir, version: &str) {
+        let rollout = Rollout {
+            version: version.to_string(),
+            ..Default::default()
+        };
+        let path = media.path().join("cfg").join(ROLLOUT_DOCUMENT);
+        rollout.write_to_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_version_is_rolled_back_to_applied_one() {
+        let media = TempDir::new().unwrap();
+        let node_config = node_config(&media);
+
+        roll_out(&media, "1");
+        let (playlist, status) = PlaylistLoader::new(&node_config).load(&node_config);
+        assert!(playlist.unwrap().music.is_some());
+        assert!(status.applied);
+        assert_eq!(status.active.as_deref(), Some("1"));
+
+        // applied version survives node restart
+        roll_out(&media, "2");
+        let mut loader = PlaylistLoader::new(&node_config);
+        let (playlist, status) = loader.load(&node_config);
+        assert!(playlist.unwrap().music.is_some());
+        assert!(!status.applied);
+        assert!(status.rolled_back);
+        assert_eq!(status.target.as_deref(), Some("2"));
+        assert_eq!(status.active.as_deref(), Some("1"));
+        assert!(status.error.unwrap().contains("volume level 500"));
+
+        roll_out(&media, "3");
+        let (playlist, status) = loader.load(&node_config);
+        assert_eq!(playlist.unwrap().music.unwrap().shuffle, Some(false));
+        assert!(status.applied);
+        assert_eq!(status.active.as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn invalid_version_fails_without_applied_one() {
+        let media = TempDir::new().unwrap();
+        let node_config = node_config(&media);
+
+        roll_out(&media, "2");
+        let (playlist, status) = PlaylistLoader::new(&node_config).load(&node_config);
+        assert!(playlist.is_err());
+        assert!(!status.applied);
+        assert!(!status.rolled_back);
+        assert_eq!(status.active, None);
+    }
+}
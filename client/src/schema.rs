@@ -0,0 +1,288 @@
+use crate::config::NodeConfig;
+use crate::merge::{self, REMOVE_KEY, REPLACE_KEY};
+use crate::playlist::Playlist;
+use schemars::schema_for;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Key which is not defined by settings types, it would be ignored
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownField {
+    pub path: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown field `{}`", self.path)?;
+        if let Some(suggestion) = self.suggestion.as_ref() {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Returns JSON Schema of playlist documents, merge directives are allowed in every table
+pub fn get_playlist_schema() -> Value {
+    let mut schema = serde_json::to_value(schema_for!(Playlist)).unwrap_or_default();
+    restrict(&mut schema, true);
+    schema
+}
+
+/// Returns JSON Schema of node settings file
+pub fn get_node_config_schema() -> Value {
+    let mut schema = serde_json::to_value(schema_for!(NodeConfig)).unwrap_or_default();
+    restrict(&mut schema, false);
+    schema
+}
+
+/// Forbids unknown keys in tables of settings structures, so editors highlight typos
+fn restrict(schema: &mut Value, directives: bool) {
+    match schema {
+        Value::Object(object) => {
+            let is_struct = object.contains_key("properties");
+            let is_map = object
+                .get("additionalProperties")
+                .is_some_and(Value::is_object);
+            if is_struct {
+                object
+                    .entry("additionalProperties")
+                    .or_insert(Value::Bool(false));
+            }
+            if directives && (is_struct || is_map) {
+                if let Some(properties) = object
+                    .entry("properties")
+                    .or_insert_with(|| json!({}))
+                    .as_object_mut()
+                {
+                    properties.insert(
+                        REMOVE_KEY.to_string(),
+                        json!({ "type": "array", "description": "Inherited keys to remove" }),
+                    );
+                    properties.insert(
+                        REPLACE_KEY.to_string(),
+                        json!({ "type": "boolean", "description": "Replace inherited table instead of merging" }),
+                    );
+                }
+            }
+            object.values_mut().for_each(|v| restrict(v, directives));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| restrict(v, directives)),
+        _ => {}
+    }
+}
+
+/// Deserializes TOML document, keys which are not defined by settings types are returned
+/// with the closest known keys
+pub fn from_str<T: DeserializeOwned>(
+    content: &str,
+    schema: &Value,
+) -> Result<(T, Vec<UnknownField>), toml::de::Error> {
+    let mut ignored: Vec<Vec<Segment>> = Vec::new();
+    let mut deserializer = toml::Deserializer::new(content);
+    let value = serde_ignored::deserialize(&mut deserializer, |path| {
+        let mut segments = Vec::new();
+        collect_segments(&path, &mut segments);
+        ignored.push(segments);
+    })?;
+
+    let fields = ignored
+        .iter()
+        .filter_map(|segments| {
+            let (last, parent) = segments.split_last()?;
+            let Segment::Key(key) = last else {
+                return None;
+            };
+            Some(UnknownField {
+                path: format_path(segments),
+                suggestion: get_suggestion(key, &get_known_keys(schema, parent)),
+            })
+        })
+        .collect();
+    Ok((value, fields))
+}
+
+fn collect_segments(path: &serde_ignored::Path, segments: &mut Vec<Segment>) {
+    match path {
+        serde_ignored::Path::Root => {}
+        serde_ignored::Path::Seq { parent, index } => {
+            collect_segments(parent, segments);
+            segments.push(Segment::Index(*index));
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            collect_segments(parent, segments);
+            segments.push(Segment::Key(key.clone()));
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => collect_segments(parent, segments),
+    }
+}
+
+/// Returns dotted path like `events.sale.fle`, array items are marked with index: `music.schedule[0]`
+fn format_path(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .fold(String::new(), |path, segment| match segment {
+            Segment::Key(key) => merge::join_path(&path, key),
+            Segment::Index(index) => format!("{}[{}]", path, index),
+        })
+}
+
+/// Returns keys defined for table at given path
+fn get_known_keys(root: &Value, path: &[Segment]) -> Vec<String> {
+    let mut schema = root;
+    for segment in path {
+        let Some(child) = get_child(root, schema, segment) else {
+            return Vec::new();
+        };
+        schema = child;
+    }
+    resolve(root, schema)
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|p| p.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Returns schema of table value or array item
+fn get_child<'a>(root: &'a Value, schema: &'a Value, segment: &Segment) -> Option<&'a Value> {
+    let schema = resolve(root, schema);
+    match segment {
+        Segment::Key(key) => schema
+            .get("properties")
+            .and_then(|p| p.get(key))
+            .or_else(|| schema.get("additionalProperties").filter(|a| a.is_object())),
+        Segment::Index(index) => match schema.get("items") {
+            Some(Value::Array(items)) => items.get(*index),
+            items => items,
+        },
+    }
+}
+
+/// Converts strings to TOML dates and times where schema expects them, other strings are kept
+pub fn parse_datetimes(value: &mut toml::Value, root: &Value) {
+    parse_datetimes_at(value, root, root);
+}
+
+fn parse_datetimes_at(value: &mut toml::Value, schema: &Value, root: &Value) {
+    let schema = resolve(root, schema);
+    match value {
+        toml::Value::String(s) => {
+            let format = schema.get("format").and_then(Value::as_str);
+            if matches!(format, Some("date" | "partial-date-time" | "date-time")) {
+                if let Ok(datetime) = s.parse::<toml::value::Datetime>() {
+                    *value = toml::Value::Datetime(datetime);
+                }
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                if let Some(child) = get_child(root, schema, &Segment::Key(key.clone())) {
+                    parse_datetimes_at(item, child, root);
+                }
+            }
+        }
+        toml::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                if let Some(child) = get_child(root, schema, &Segment::Index(index)) {
+                    parse_datetimes_at(item, child, root);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Follows references to definitions and optional values
+fn resolve<'a>(root: &'a Value, mut schema: &'a Value) -> &'a Value {
+    loop {
+        if let Some(definition) = schema
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|r| r.strip_prefix("#/definitions/"))
+            .and_then(|name| root.get("definitions")?.get(name))
+        {
+            schema = definition;
+            continue;
+        }
+        let variants = schema.get("anyOf").or_else(|| schema.get("allOf"));
+        if let Some(variant) = variants
+            .and_then(Value::as_array)
+            .and_then(|v| v.iter().find(|v| v.get("type") != Some(&json!("null"))))
+        {
+            schema = variant;
+            continue;
+        }
+        return schema;
+    }
+}
+
+/// Returns the closest known key if it differs from given one in about third of letters or less
+fn get_suggestion(key: &str, known: &[String]) -> Option<String> {
+    let limit = (key.chars().count() / 3).max(1);
+    known
+        .iter()
+        .map(|k| (strsim::damerau_levenshtein(key, k), k))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, k)| k.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unknown_fields(content: &str) -> Vec<String> {
+        let (_, fields) = from_str::<Playlist>(content, &get_playlist_schema()).unwrap();
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn unknown_fields_are_suggested_at_every_depth() {
+        let fields = unknown_fields(
+            "[advertisment]\n[music]\nshufle = true\n[events.sale]\ntime = 10:00:00\nfle = 'a.mp3'\n[volume.offsets]\nmusik = 3",
+        );
+        assert_eq!(
+            fields,
+            [
+                "unknown field `advertisment`, did you mean `advertizement`?",
+                "unknown field `music.shufle`, did you mean `shuffle`?",
+                "unknown field `events.sale.fle`, did you mean `file`?",
+                "unknown field `volume.offsets.musik`, did you mean `music`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn unrelated_field_has_no_suggestion() {
+        assert_eq!(
+            unknown_fields("[music]\ncompletely_different = 1"),
+            ["unknown field `music.completely_different`"]
+        );
+    }
+
+    #[test]
+    fn map_keys_are_not_unknown_fields() {
+        let content = "[advertizement.schedule]\n'00:10:00' = ['ads']\n[working_hours.exceptions]\n2025-01-01 = [10:00:00, 18:00:00]";
+        assert!(unknown_fields(content).is_empty());
+    }
+
+    #[test]
+    fn schema_forbids_unknown_keys_and_allows_merge_directives() {
+        let schema = get_playlist_schema();
+        assert_eq!(schema["additionalProperties"], json!(false));
+        let music = &schema["definitions"]["Music"];
+        assert_eq!(music["additionalProperties"], json!(false));
+        assert!(music["properties"].get(REMOVE_KEY).is_some());
+        assert!(get_node_config_schema()["properties"]
+            .get(REPLACE_KEY)
+            .is_none());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::control::ControlCommand;
+use crate::error::{Error, Result};
+use crate::silence::SilentInterval;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use vlc::EventType;
+
+/// Node media outputs: main player for music and scheduled content, voice player for voice over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Main,
+    Voice,
+}
+
+/// Signals which wake up player loop
+#[derive(Debug)]
+pub enum Signal {
+    MediaPlaying(Output),
+    MediaEnded(Output),
+    MediaError(Output),
+    Command(ControlCommand),
+    SilenceFound(PathBuf, Vec<SilentInterval>),
+}
+
+impl Signal {
+    /// Returns output for playback signals
+    pub fn output(&self) -> Option<Output> {
+        match self {
+            Signal::MediaPlaying(o) | Signal::MediaEnded(o) | Signal::MediaError(o) => Some(*o),
+            _ => None,
+        }
+    }
+}
+
+/// Attaches backend event handlers, which send playback signals of given output
+pub fn attach_media_events(
+    media_player: &vlc::MediaPlayer,
+    output: Output,
+    sender: &Sender<Signal>,
+) -> Result<()> {
+    let event_manager = media_player.event_manager();
+    for event_type in [
+        EventType::MediaPlayerPlaying,
+        EventType::MediaPlayerEndReached,
+        EventType::MediaPlayerEncounteredError,
+    ] {
+        let sender = sender.clone();
+        event_manager
+            .attach(event_type, move |_, _| {
+                let signal = match event_type {
+                    EventType::MediaPlayerPlaying => Signal::MediaPlaying(output),
+                    EventType::MediaPlayerEndReached => Signal::MediaEnded(output),
+                    _ => Signal::MediaError(output),
+                };
+                let _ = sender.send(signal);
+            })
+            .map_err(|_| Error::Backend(format!("cannot attach {:?} handler", output)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::thread;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Samples with lower peak amplitude are treated as silence (-60 dBFS)
+const SILENCE_LEVEL: f32 = 0.001;
+
+/// Silent interval inside media file, in milliseconds
+#[derive(Debug, Clone, Copy)]
+pub struct SilentInterval {
+    pub start: i64,
+    pub end: i64,
+}
+
+/// Decodes media file in background thread, silent intervals longer than given duration are passed to callback
+pub fn spawn_analysis(
+    path: PathBuf,
+    min_duration_ms: i64,
+    on_done: impl FnOnce(PathBuf, Vec<SilentInterval>) + Send + 'static,
+) {
+    thread::spawn(move || {
+        let intervals = find_silence(&path, min_duration_ms);
+        on_done(path, intervals);
+    });
+}
+
+/// Returns silent intervals longer than given duration, files which can't be decoded have no intervals
+pub fn find_silence(path: &Path, min_duration_ms: i64) -> Vec<SilentInterval> {
+    let mut intervals: Vec<SilentInterval> = Vec::new();
+
+    let Ok(file) = File::open(path) else {
+        return intervals;
+    };
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let Ok(probed) = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) else {
+        return intervals;
+    };
+
+    let mut format = probed.format;
+    let Some(track) = format.default_track() else {
+        return intervals;
+    };
+    let track_id = track.id;
+    let Some(sample_rate) = track.codec_params.sample_rate else {
+        return intervals;
+    };
+    let Ok(mut decoder) =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())
+    else {
+        return intervals;
+    };
+
+    let to_ms = |frames: u64| (frames * 1000 / sample_rate as u64) as i64;
+    let mut push_interval = |start: u64, end: u64| {
+        if to_ms(end - start) >= min_duration_ms {
+            intervals.push(SilentInterval {
+                start: to_ms(start),
+                end: to_ms(end),
+            });
+        }
+    };
+
+    let mut total_frames: u64 = 0;
+    let mut silence_start: Option<u64> = None;
+    let mut sample_buffer: Option<SampleBuffer<f32>> = None;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(d) => d,
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(_) => break,
+        };
+
+        let frames = decoded.frames() as u64;
+        let buffer = match sample_buffer.as_mut() {
+            Some(b) if b.capacity() >= decoded.capacity() => b,
+            _ => sample_buffer.insert(SampleBuffer::new(
+                decoded.capacity() as u64,
+                *decoded.spec(),
+            )),
+        };
+        buffer.copy_interleaved_ref(decoded);
+
+        let peak = buffer
+            .samples()
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()));
+        if peak < SILENCE_LEVEL {
+            silence_start.get_or_insert(total_frames);
+        } else if let Some(start) = silence_start.take() {
+            push_interval(start, total_frames);
+        }
+        total_frames += frames;
+    }
+    if let Some(start) = silence_start {
+        push_interval(start, total_frames);
+    }
+
+    intervals
+}
@@ -0,0 +1,153 @@
+use crate::config::NodeConfig;
+use chrono::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Played tracks history length
+const HISTORY_LENGTH: usize = 50;
+
+/// Event occurrence which was started, but was not finished
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingEvent {
+    pub id: String,
+    pub datetime: NaiveDateTime,
+}
+
+/// Player state, saved to restore playback after node restart
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SavedState {
+    pub saved_at: Option<NaiveDateTime>,
+    pub current_track: Option<PathBuf>,
+    pub position: Option<i64>,
+    pub next_track_index: usize,
+    pub music_files: Vec<PathBuf>,
+    pub history: Vec<PathBuf>,
+    pub last_advertizement_slot: Option<NaiveDateTime>,
+    pub last_time_announcement: Option<NaiveDateTime>,
+    pub interrupted_advertizement: Option<Vec<PathBuf>>,
+    pub pending_event: Option<PendingEvent>,
+}
+
+/// Per-node player state file
+pub struct StateStore {
+    file_path: PathBuf,
+    last_saved: Option<SavedState>,
+}
+
+impl StateStore {
+    pub fn open(node_config: &NodeConfig) -> StateStore {
+        let media_folder = &node_config.media.folder;
+        let node_name = node_config.node.name.clone().unwrap_or_default();
+        let file_path = Path::new(media_folder)
+            .join("state")
+            .join(format!("player_{}.toml", node_name));
+        StateStore {
+            file_path,
+            last_saved: None,
+        }
+    }
+
+    /// Returns saved state if it was saved since given start of working day
+    pub fn restore(&self, working_day_start: NaiveDateTime) -> Option<SavedState> {
+        let content = fs::read_to_string(&self.file_path).ok()?;
+        let state = match toml::from_str::<SavedState>(&content) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("cannot parse player state file {:?}: {}", self.file_path, e);
+                return None;
+            }
+        };
+        if state
+            .saved_at
+            .is_none_or(|saved_at| saved_at < working_day_start)
+        {
+            log::info!("player state is outdated and won't be restored");
+            return None;
+        }
+        log::info!("player state restored from {:?}", self.file_path);
+        Some(state)
+    }
+
+    /// Writes state unless it is the same as the last written one
+    pub fn save(&mut self, state: &SavedState) {
+        if self.last_saved.as_ref() == Some(state) {
+            return;
+        }
+        self.last_saved = Some(state.clone());
+        if let Some(parent) = self.file_path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let state = SavedState {
+            saved_at: Some(Local::now().naive_local()),
+            ..state.clone()
+        };
+        match toml::to_string(&state) {
+            Ok(c) => {
+                // file is replaced at once, so power cut can't leave it half-written
+                let tmp_path = self.file_path.with_extension("tmp");
+                if let Err(e) =
+                    fs::write(&tmp_path, c).and_then(|_| fs::rename(&tmp_path, &self.file_path))
+                {
+                    log::error!("cannot write player state file {:?}: {}", self.file_path, e);
+                }
+            }
+            Err(e) => log::error!("cannot serialize player state: {}", e),
+        }
+    }
+}
+
+/// Adds track to played tracks history, the oldest tracks are dropped
+pub fn push_history(history: &mut Vec<PathBuf>, track: &Path) {
+    history.push(track.to_path_buf());
+    if history.len() > HISTORY_LENGTH {
+        history.remove(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn open_store(media: &TempDir) -> StateStore {
+        let config = format!(
+            "[media]\nfolder = {:?}\n[node]\nname = \"pc101\"",
+            media.path().to_string_lossy()
+        );
+        StateStore::open(&toml::from_str(&config).unwrap())
+    }
+
+    #[test]
+    fn state_is_restored_within_working_day() {
+        let media = TempDir::new().unwrap();
+        let mut store = open_store(&media);
+        let state = SavedState {
+            next_track_index: 3,
+            ..Default::default()
+        };
+        store.save(&state);
+
+        let now = Local::now().naive_local();
+        let restored = store.restore(now - chrono::Duration::hours(20)).unwrap();
+        assert_eq!(restored.next_track_index, 3);
+        assert!(store.restore(now + chrono::Duration::hours(1)).is_none());
+    }
+
+    #[test]
+    fn unchanged_state_is_not_written_again() {
+        let media = TempDir::new().unwrap();
+        let mut store = open_store(&media);
+        let state = SavedState::default();
+        store.save(&state);
+        fs::remove_file(&store.file_path).unwrap();
+
+        store.save(&state);
+        assert!(!store.file_path.exists());
+        store.save(&SavedState {
+            position: Some(1000),
+            ..state
+        });
+        assert!(store.file_path.exists());
+    }
+}
@@ -0,0 +1,591 @@
+use crate::config::NodeConfig;
+use crate::error::{Error, Result};
+use crate::manifest::{self, hash_file, Manifest, ManifestEntry};
+use crate::peers::{self, PeerDirectory, SharedLibrary};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Sync interval if not defined in node settings, in seconds
+const DEFAULT_SYNC_INTERVAL: u64 = 300;
+
+/// Manifest file name on content server
+const MANIFEST_NAME: &str = "manifest.json";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Size of file part requested from single peer at once
+const PEER_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Characters escaped in media file URL path
+const PATH_ESCAPE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Result of single synchronization round
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub downloaded: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+}
+
+/// Mirrors media files listed in content server manifest into media folder
+pub struct Syncer {
+    url: String,
+    media_folder: PathBuf,
+    /// The last applied manifest, files listed in it were placed by sync
+    applied_path: PathBuf,
+    downloads_folder: PathBuf,
+    agent: ureq::Agent,
+    /// Synchronized files shared with peers
+    library: SharedLibrary,
+    peers: Option<PeerDirectory>,
+}
+
+/// Starts periodic media synchronization (if configured), node keeps playing local copy while server is unreachable
+pub fn start(node_config: &NodeConfig) {
+    let Some(mut syncer) = Syncer::new(node_config) else {
+        return;
+    };
+    syncer.start_peers(node_config);
+    let interval = Duration::from_secs(
+        node_config
+            .sync
+            .as_ref()
+            .and_then(|s| s.interval)
+            .unwrap_or(DEFAULT_SYNC_INTERVAL),
+    );
+    log::info!(
+        "media is synchronized from {} every {:?}",
+        syncer.url,
+        interval
+    );
+
+    thread::spawn(move || loop {
+        if let Err(e) = syncer.run() {
+            e.log();
+        }
+        thread::sleep(interval);
+    });
+}
+
+impl Syncer {
+    pub fn new(node_config: &NodeConfig) -> Option<Syncer> {
+        let sync = node_config.sync.as_ref()?;
+        let node_name = node_config.node.name.clone().unwrap_or_default();
+        let media_folder = PathBuf::from(&node_config.media.folder);
+        let syncer = Syncer {
+            url: sync.url.trim_end_matches('/').to_string(),
+            applied_path: media_folder
+                .join("state")
+                .join(format!("sync_{}.json", node_name)),
+            downloads_folder: media_folder.join("state").join("downloads"),
+            media_folder,
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .timeout_read(READ_TIMEOUT)
+                .build(),
+            library: SharedLibrary::default(),
+            peers: None,
+        };
+        let applied = Manifest::read_from_file(&syncer.applied_path).unwrap_or_default();
+        syncer.update_library(&applied.files);
+        Some(syncer)
+    }
+
+    /// Shares synchronized files with peers from the same site and fetches files from them (if configured)
+    pub fn start_peers(&mut self, node_config: &NodeConfig) {
+        self.peers = peers::start(node_config, self.library.clone());
+    }
+
+    fn update_library(&self, files: &[ManifestEntry]) {
+        if let Ok(mut library) = self.library.write() {
+            library.clear();
+            for entry in files {
+                if let Some(path) = self.get_local_path(&entry.path) {
+                    library.insert(entry.sha256.clone(), (path, entry.size));
+                }
+            }
+        }
+    }
+
+    /// Downloads changed files and deletes files which are no longer listed in manifest
+    pub fn run(&self) -> Result<SyncReport> {
+        let manifest = self.fetch_manifest()?;
+        let applied: HashMap<String, ManifestEntry> = Manifest::read_from_file(&self.applied_path)
+            .unwrap_or_default()
+            .files
+            .into_iter()
+            .map(|e| (e.path.clone(), e))
+            .collect();
+
+        let mut report = SyncReport::default();
+        let mut placed: Vec<ManifestEntry> = Vec::new();
+        for entry in manifest.files.iter() {
+            let Some(path) = self.get_local_path(&entry.path) else {
+                log::warn!(
+                    "sync: path {:?} is outside media folder, skipped",
+                    entry.path
+                );
+                report.failed += 1;
+                continue;
+            };
+            if self.is_up_to_date(entry, applied.get(&entry.path), &path) {
+                report.unchanged += 1;
+                placed.push(entry.clone());
+                continue;
+            }
+            match self.download(entry, &path) {
+                Ok(()) => {
+                    log::info!(file:? = path; "sync: {:?} downloaded", path);
+                    report.downloaded += 1;
+                    placed.push(entry.clone());
+                }
+                Err(e) => {
+                    log::warn!("sync: cannot download {:?}: {}", entry.path, e);
+                    report.failed += 1;
+                    // old version is still in place and may be deleted later
+                    if let Some(old) = applied.get(&entry.path) {
+                        placed.push(old.clone());
+                    }
+                }
+            }
+        }
+
+        // only files placed by sync are deleted, local files (logs, state) are kept
+        for path in applied.keys() {
+            if manifest.files.iter().any(|e| &e.path == path) {
+                continue;
+            }
+            if let Some(local_path) = self.get_local_path(path) {
+                match fs::remove_file(&local_path) {
+                    Ok(()) => {
+                        log::info!("sync: {:?} deleted", local_path);
+                        report.deleted += 1;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => log::warn!("sync: cannot delete {:?}: {}", local_path, e),
+                }
+            }
+        }
+
+        self.update_library(&placed);
+        Manifest { files: placed }
+            .write_to_file(&self.applied_path)
+            .map_err(|e| Error::Sync(format!("cannot write {:?}: {}", self.applied_path, e)))?;
+        log::info!(
+            "sync: {} downloaded, {} deleted, {} unchanged, {} failed",
+            report.downloaded,
+            report.deleted,
+            report.unchanged,
+            report.failed
+        );
+        Ok(report)
+    }
+
+    fn fetch_manifest(&self) -> Result<Manifest> {
+        let url = format!("{}/{}", self.url, MANIFEST_NAME);
+        self.agent
+            .get(&url)
+            .call()
+            .map_err(|e| Error::Sync(format!("{}: {}", url, e)))?
+            .into_json()
+            .map_err(|e| Error::Sync(format!("{}: {}", url, e)))
+    }
+
+    /// Returns path inside media folder, paths leaving media folder are rejected
+    fn get_local_path(&self, path: &str) -> Option<PathBuf> {
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            Some(self.media_folder.join(relative))
+        } else {
+            None
+        }
+    }
+
+    /// Files placed by previous sync are checked by size, other existing files are hashed
+    fn is_up_to_date(
+        &self,
+        entry: &ManifestEntry,
+        applied: Option<&ManifestEntry>,
+        path: &Path,
+    ) -> bool {
+        let Ok(metadata) = fs::metadata(path) else {
+            return false;
+        };
+        if metadata.len() != entry.size {
+            return false;
+        }
+        match applied {
+            Some(applied) if applied.sha256 == entry.sha256 => true,
+            _ => hash_file(path).is_ok_and(|h| h == entry.sha256),
+        }
+    }
+
+    /// Downloads file into partial file named by its hash, so interrupted download is resumed,
+    /// verified file is renamed into media folder at once
+    fn download(&self, entry: &ManifestEntry, path: &Path) -> io::Result<()> {
+        // hash is a part of file path
+        if !manifest::is_valid_sha256(&entry.sha256) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid SHA-256 {:?}", entry.sha256),
+            ));
+        }
+        fs::create_dir_all(&self.downloads_folder)?;
+        let part_path = self.downloads_folder.join(format!("{}.part", entry.sha256));
+
+        let peers = self
+            .peers
+            .as_ref()
+            .map(|p| p.get_peers())
+            .unwrap_or_default();
+        let from_peers = self.fetch(entry, &part_path, &peers)?;
+        if let Err(e) = verify_download(entry, &part_path) {
+            if !from_peers {
+                return Err(e);
+            }
+            log::warn!(
+                "sync: {:?} received from peers does not match manifest, downloading from content server",
+                entry.path
+            );
+            self.fetch(entry, &part_path, &[])?;
+            verify_download(entry, &part_path)?;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&part_path, path)
+    }
+
+    /// Appends missing part of file: chunks are taken from peers while some peer has the file,
+    /// the rest from content server; returns true if any chunk was received from peer
+    fn fetch(
+        &self,
+        entry: &ManifestEntry,
+        part_path: &Path,
+        peers: &[SocketAddr],
+    ) -> io::Result<bool> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(part_path)?;
+        if file.metadata()?.len() > entry.size {
+            file.set_len(0)?;
+        }
+
+        let mut from_peers = false;
+        let mut peers = peers.iter();
+        let mut peer = peers.next();
+        loop {
+            let offset = file.metadata()?.len();
+            if offset >= entry.size {
+                return Ok(from_peers);
+            }
+            let Some(address) = peer else {
+                break;
+            };
+            let url = format!("http://{}/sha256/{}", address, entry.sha256);
+            let len = PEER_CHUNK_SIZE.min(entry.size - offset);
+            match self.fetch_range(&url, offset, len, &mut file) {
+                Ok(n) if n > 0 => from_peers = true,
+                Ok(_) => peer = peers.next(),
+                Err(e) => {
+                    log::debug!(
+                        "sync: cannot fetch {:?} from peer {}: {}",
+                        entry.path,
+                        address,
+                        e
+                    );
+                    peer = peers.next();
+                }
+            }
+        }
+
+        let offset = file.metadata()?.len();
+        let url = format!(
+            "{}/{}",
+            self.url,
+            utf8_percent_encode(&entry.path, PATH_ESCAPE)
+        );
+        self.fetch_range(&url, offset, entry.size - offset, &mut file)?;
+        file.sync_all()?;
+        Ok(from_peers)
+    }
+
+    /// Appends given range of remote file, servers ignoring range are supported; returns count of received bytes
+    fn fetch_range(&self, url: &str, offset: u64, len: u64, file: &mut File) -> io::Result<u64> {
+        let response = self
+            .agent
+            .get(url)
+            .set("Range", &format!("bytes={}-{}", offset, offset + len - 1))
+            .call()
+            .map_err(io::Error::other)?;
+        let partial = response.status() == 206;
+        let mut reader = response.into_reader();
+        if !partial {
+            io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+        }
+        io::copy(&mut reader.take(len), file)
+    }
+}
+
+/// Checks downloaded file against manifest, mismatching file is deleted
+fn verify_download(entry: &ManifestEntry, part_path: &Path) -> io::Result<()> {
+    let size = fs::metadata(part_path)?.len();
+    let hash = hash_file(part_path)?;
+    if size != entry.size || hash != entry.sha256 {
+        fs::remove_file(part_path)?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("size {} or hash {} does not match manifest", size, hash),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    /// Content server serving manifest and files from folder, counts media file requests
+    struct ContentServer {
+        url: String,
+        folder: TempDir,
+        manifest: Arc<Mutex<Manifest>>,
+        file_requests: Arc<AtomicUsize>,
+    }
+
+    impl ContentServer {
+        fn start() -> ContentServer {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let folder = TempDir::new().unwrap();
+            let manifest = Arc::new(Mutex::new(Manifest::default()));
+            let file_requests = Arc::new(AtomicUsize::new(0));
+
+            let root = folder.path().to_path_buf();
+            let (served_manifest, requests) = (manifest.clone(), file_requests.clone());
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let mut request_line = String::new();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    reader.read_line(&mut request_line).unwrap();
+                    let mut header = String::new();
+                    while reader.read_line(&mut header).unwrap() > 2 {
+                        header.clear();
+                    }
+                    let path = request_line.split_whitespace().nth(1).unwrap();
+                    let body = if path == "/manifest.json" {
+                        serde_json::to_vec(&*served_manifest.lock().unwrap()).unwrap()
+                    } else {
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        let path = path.trim_start_matches('/').replace("%20", " ");
+                        fs::read(root.join(path)).unwrap()
+                    };
+                    // range is ignored, client must skip received part itself
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    )
+                    .unwrap();
+                    stream.write_all(&body).unwrap();
+                }
+            });
+            ContentServer {
+                url,
+                folder,
+                manifest,
+                file_requests,
+            }
+        }
+
+        fn add_file(&self, path: &str, content: &[u8]) {
+            let file_path = self.folder.path().join(path);
+            fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            fs::write(&file_path, content).unwrap();
+            self.manifest.lock().unwrap().files.push(ManifestEntry {
+                path: path.to_string(),
+                size: content.len() as u64,
+                sha256: hash_file(&file_path).unwrap(),
+            });
+        }
+
+        fn file_requests(&self) -> usize {
+            self.file_requests.load(Ordering::SeqCst)
+        }
+    }
+
+    struct Node {
+        media: TempDir,
+        syncer: Syncer,
+    }
+
+    fn start_node(name: &str, site: &str, server: &ContentServer) -> Node {
+        let media = TempDir::new().unwrap();
+        let config = format!(
+            r#"
+            [media]
+            folder = "{}"
+            [node]
+            name = "{}"
+            [sync]
+            url = "{}"
+            [sync.peers]
+            address = "127.0.0.1:0"
+            site = "{}-{}"
+            interface = "127.0.0.1"
+            "#,
+            media.path().display(),
+            name,
+            server.url,
+            // peers of other test runs are not found
+            site,
+            std::process::id()
+        );
+        let node_config: NodeConfig = toml::from_str(&config).unwrap();
+        let mut syncer = Syncer::new(&node_config).unwrap();
+        syncer.start_peers(&node_config);
+        Node { media, syncer }
+    }
+
+    fn wait_peers(node: &Node, count: usize) {
+        let started = Instant::now();
+        while node.syncer.peers.as_ref().unwrap().get_peers().len() < count {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "peers not found"
+            );
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn files_are_fetched_from_peers_of_the_same_site() {
+        let server = ContentServer::start();
+        server.add_file("music/track 1.mp3", &[1u8; 300_000]);
+        server.add_file("adv/ad.mp3", b"advertizement");
+
+        let first = start_node("first", "store", &server);
+        let report = first.syncer.run().unwrap();
+        assert_eq!(report.downloaded, 2);
+        assert_eq!(server.file_requests(), 2);
+
+        let second = start_node("second", "store", &server);
+        let third = start_node("third", "store", &server);
+        let other_site = start_node("other", "office", &server);
+        wait_peers(&second, 2);
+        wait_peers(&third, 2);
+
+        assert_eq!(second.syncer.run().unwrap().downloaded, 2);
+        assert_eq!(third.syncer.run().unwrap().downloaded, 2);
+        assert_eq!(server.file_requests(), 2);
+        assert!(other_site
+            .syncer
+            .peers
+            .as_ref()
+            .unwrap()
+            .get_peers()
+            .is_empty());
+        for node in [&second, &third] {
+            let content = fs::read(node.media.path().join("music/track 1.mp3")).unwrap();
+            assert_eq!(content, vec![1u8; 300_000]);
+        }
+
+        // file which no peer has is fetched from content server
+        server.add_file("music/track 2.mp3", b"new track");
+        assert_eq!(second.syncer.run().unwrap().downloaded, 1);
+        assert_eq!(server.file_requests(), 3);
+        assert_eq!(third.syncer.run().unwrap().downloaded, 1);
+        assert_eq!(server.file_requests(), 3);
+    }
+
+    #[test]
+    fn interrupted_download_is_resumed() {
+        let server = ContentServer::start();
+        let content: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        server.add_file("music/track.mp3", &content);
+
+        let node = start_node("resumed", "resumed", &server);
+        let sha256 = server.manifest.lock().unwrap().files[0].sha256.clone();
+        fs::create_dir_all(&node.syncer.downloads_folder).unwrap();
+        fs::write(
+            node.syncer
+                .downloads_folder
+                .join(format!("{}.part", sha256)),
+            &content[..40_000],
+        )
+        .unwrap();
+
+        assert_eq!(node.syncer.run().unwrap().downloaded, 1);
+        let placed = fs::read(node.media.path().join("music/track.mp3")).unwrap();
+        assert_eq!(placed, content);
+    }
+
+    #[test]
+    fn unlisted_synchronized_files_are_deleted() {
+        let server = ContentServer::start();
+        server.add_file("music/old.mp3", b"old");
+        let node = start_node("deleting", "deleting", &server);
+        node.syncer.run().unwrap();
+        fs::write(node.media.path().join("local.txt"), b"local").unwrap();
+
+        server.manifest.lock().unwrap().files.clear();
+        assert_eq!(node.syncer.run().unwrap().deleted, 1);
+        assert!(!node.media.path().join("music/old.mp3").exists());
+        assert!(node.media.path().join("local.txt").exists());
+    }
+
+    #[test]
+    fn entries_with_invalid_hash_are_refused() {
+        let server = ContentServer::start();
+        server.add_file("music/track.mp3", b"track");
+        let traversal = "../../victim".to_string();
+        server.manifest.lock().unwrap().files[0].sha256 = traversal.clone();
+
+        let node = start_node("refusing", "refusing", &server);
+        let victim = node.media.path().join("victim.part");
+        fs::write(&victim, b"victim").unwrap();
+        assert!(node.syncer.run().is_err());
+
+        let entry = ManifestEntry {
+            path: "music/track.mp3".to_string(),
+            size: 5,
+            sha256: traversal,
+        };
+        let error = node
+            .syncer
+            .download(&entry, &node.media.path().join("music/track.mp3"))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&victim).unwrap(), b"victim");
+        assert_eq!(server.file_requests(), 0);
+    }
+}
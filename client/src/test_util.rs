@@ -0,0 +1,23 @@
+use crate::config::Node;
+use chrono::prelude::*;
+
+pub fn date(s: &str) -> NaiveDate {
+    s.parse().unwrap()
+}
+
+pub fn time(s: &str) -> NaiveTime {
+    s.parse().unwrap()
+}
+
+pub fn datetime(s: &str) -> NaiveDateTime {
+    s.parse().unwrap()
+}
+
+/// Returns node with given name and groups, without tags
+pub fn node(name: &str, groups: &[&str]) -> Node {
+    Node {
+        name: Some(name.to_string()),
+        groups: Some(groups.iter().map(|g| g.to_string()).collect()),
+        tags: None,
+    }
+}
@@ -0,0 +1,38 @@
+use crate::config::NodeConfig;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Synthesizes speech for given text using external command from node config,
+/// returns path to synthesized audio file
+pub fn synthesize(node_config: &NodeConfig, text: &str) -> Option<PathBuf> {
+    let tts = node_config.tts.as_ref()?;
+    let (program, args) = tts.command.split_first()?;
+
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    let file_path =
+        std::env::temp_dir().join(format!("distributed_player_tts_{:x}.wav", hasher.finish()));
+    if file_path.exists() {
+        return Some(file_path);
+    }
+
+    let file_path_str = file_path.to_string_lossy();
+    let args: Vec<String> = args
+        .iter()
+        .map(|a| a.replace("{text}", text).replace("{file}", &file_path_str))
+        .collect();
+
+    match Command::new(program).args(&args).status() {
+        Ok(status) if status.success() && file_path.exists() => Some(file_path),
+        Ok(status) => {
+            log::error!("tts command exited with {}", status);
+            None
+        }
+        Err(e) => {
+            log::error!("cannot run tts command {:?}: {}", program, e);
+            None
+        }
+    }
+}